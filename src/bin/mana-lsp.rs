@@ -0,0 +1,36 @@
+use mana::lsp::{
+    transport::{read_message, write_message},
+    Server,
+};
+use std::{io, process::ExitCode};
+
+fn main() -> ExitCode {
+    let mut input = io::stdin().lock();
+    let mut output = io::stdout().lock();
+    let mut server = Server::new();
+
+    while !server.exited() {
+        let message = match read_message(&mut input) {
+            Ok(Some(message)) => message,
+            Ok(None) => break,
+            Err(e) => {
+                eprintln!("error: {}", e);
+                return ExitCode::FAILURE;
+            }
+        };
+
+        for reply in server.handle(&message) {
+            if let Err(e) = write_message(&mut output, &reply) {
+                eprintln!("error: {}", e);
+                return ExitCode::FAILURE;
+            }
+        }
+    }
+
+    // Clients expect a failure when the server exits without a shutdown
+    if server.shutdown() {
+        ExitCode::SUCCESS
+    } else {
+        ExitCode::FAILURE
+    }
+}
@@ -0,0 +1,82 @@
+use crate::{compiler::effects::Effect, vm::instructions::Inst};
+
+/// A word of the language that maps directly to a VM instruction
+#[derive(Debug)]
+pub struct Builtin {
+    pub name: &'static str,
+    pub inst: Inst,
+    /// None for words whose effect depends on the values they're given
+    pub effect: Option<Effect>,
+}
+
+const fn builtin(name: &'static str, inst: Inst, inputs: usize, outputs: usize) -> Builtin {
+    Builtin {
+        name,
+        inst,
+        effect: Some(Effect::new(inputs, outputs)),
+    }
+}
+
+const fn dynamic(name: &'static str, inst: Inst) -> Builtin {
+    Builtin {
+        name,
+        inst,
+        effect: None,
+    }
+}
+
+pub const BUILTINS: &[Builtin] = &[
+    // Stack
+    builtin("dup", Inst::Dup, 1, 2),
+    builtin("drop", Inst::Drop, 1, 0),
+    builtin("swap", Inst::Swap, 2, 2),
+    // Primitives
+    builtin("true", Inst::PushB(true), 0, 1),
+    builtin("false", Inst::PushB(false), 0, 1),
+    builtin("int", Inst::IntoInt, 1, 1),
+    builtin("float", Inst::IntoFloat, 1, 1),
+    builtin("str", Inst::IntoStr, 1, 1),
+    // Str
+    builtin("concat", Inst::Concat, 2, 1),
+    // List
+    builtin("List.new", Inst::PushList, 0, 1),
+    builtin("List.push", Inst::ListPush, 2, 1),
+    builtin("List.pop", Inst::ListPop, 1, 1),
+    builtin("List.get", Inst::ListGet, 2, 1),
+    builtin("List.set", Inst::ListSet, 3, 1),
+    builtin("List.len", Inst::ListLen, 1, 1),
+    // Table
+    builtin("Table.new", Inst::PushTable, 0, 1),
+    builtin("Table.get", Inst::TableGet, 2, 1),
+    builtin("Table.set", Inst::TableSet, 3, 1),
+    builtin("Table.keys", Inst::TableKeys, 1, 1),
+    builtin("Table.len", Inst::TableLen, 1, 1),
+    // Meta
+    builtin("Meta.get", Inst::LoadMeta, 1, 1),
+    builtin("Meta.set", Inst::StoreMeta, 2, 1),
+    // Boolean Operations
+    builtin("and", Inst::And, 2, 1),
+    builtin("or", Inst::Or, 2, 1),
+    builtin("xor", Inst::Xor, 2, 1),
+    builtin("not", Inst::Not, 1, 1),
+    // Arithmetic Operations
+    builtin("+", Inst::Add, 2, 1),
+    builtin("-", Inst::Sub, 2, 1),
+    builtin("*", Inst::Mul, 2, 1),
+    builtin("/", Inst::Div, 2, 1),
+    builtin("%", Inst::Mod, 2, 1),
+    // Comparisons
+    builtin("=", Inst::Equal, 2, 1),
+    builtin("!=", Inst::NotEqual, 2, 1),
+    builtin("<", Inst::LessThan, 2, 1),
+    builtin(">", Inst::GreaterThan, 2, 1),
+    builtin("<=", Inst::LessEqual, 2, 1),
+    builtin(">=", Inst::GreaterEqual, 2, 1),
+    // Functions
+    dynamic("call", Inst::Call),
+    builtin("bind", Inst::Bind, 2, 1),
+];
+
+pub fn find_builtin(name: &str) -> Option<&'static Builtin> {
+    BUILTINS.iter().find(|b| b.name == name)
+}
@@ -0,0 +1,115 @@
+use crate::{
+    lang::ast::{Expr, ExprKind},
+    vm::{emitter::Emitter, function::Function},
+};
+
+/// Words compiled to branches when their quotations are literal
+pub const WORDS: &[&str] = &["if", "times", "while"];
+
+/// A control word applied to literal quotations, which the compiler inlines
+/// into branches rather than calls
+#[derive(Debug, Clone, Copy)]
+pub enum Control<'a> {
+    /// `cond { then } { otherwise } if`
+    If {
+        then: &'a [Expr],
+        otherwise: &'a [Expr],
+    },
+    /// `n { body } times`
+    Times { body: &'a [Expr] },
+    /// `{ cond } { body } while`
+    While { cond: &'a [Expr], body: &'a [Expr] },
+}
+
+impl<'a> Control<'a> {
+    /// The word applied to the quotations
+    pub fn word(&self) -> &'static str {
+        match self {
+            Control::If { .. } => "if",
+            Control::Times { .. } => "times",
+            Control::While { .. } => "while",
+        }
+    }
+
+    /// The control word starting the expressions, with the expressions it
+    /// spans, the word being the last one. Shadowed words are left to the
+    /// definitions or locals shadowing them
+    pub fn find(
+        exprs: &'a [Expr],
+        shadowed: impl Fn(&str) -> bool,
+    ) -> Option<(Control<'a>, &'a [Expr])> {
+        let quotation = |idx: usize| match &exprs.get(idx)?.kind {
+            ExprKind::Closure(body) => Some(body.as_slice()),
+            _ => None,
+        };
+        let word = |idx: usize| match &exprs.get(idx)?.kind {
+            ExprKind::Term(word) if !shadowed(word) => Some(word.as_str()),
+            _ => None,
+        };
+
+        let first = quotation(0)?;
+        let (control, len) = match (quotation(1), word(1), word(2)) {
+            (_, Some("times"), _) => (Control::Times { body: first }, 2),
+            (Some(otherwise), _, Some("if")) => (
+                Control::If {
+                    then: first,
+                    otherwise,
+                },
+                3,
+            ),
+            (Some(body), _, Some("while")) => (Control::While { cond: first, body }, 3),
+            _ => return None,
+        };
+        Some((control, &exprs[..len]))
+    }
+}
+
+/// Function running a control word on quotations only known at runtime
+pub fn runtime_function(word: &str) -> Option<Function> {
+    let mut e = Emitter::new();
+    match word {
+        "if" => {
+            let (cond, then, otherwise) = (e.local_new(), e.local_new(), e.local_new());
+            e.local_store(otherwise).local_store(then).local_store(cond);
+            e.local_load(cond).if_else(
+                |e| {
+                    e.local_load(then).call();
+                },
+                |e| {
+                    e.local_load(otherwise).call();
+                },
+            );
+        }
+        "times" => {
+            let (count, body) = (e.local_new(), e.local_new());
+            e.local_store(body).local_store(count);
+            e.while_loop(
+                |e| {
+                    e.local_load(count).push_int(0).greater_than();
+                },
+                |e| {
+                    e.local_load(body)
+                        .call()
+                        .local_load(count)
+                        .push_int(1)
+                        .sub()
+                        .local_store(count);
+                },
+            );
+        }
+        "while" => {
+            let (cond, body) = (e.local_new(), e.local_new());
+            e.local_store(body).local_store(cond);
+            e.while_loop(
+                |e| {
+                    e.local_load(cond).call();
+                },
+                |e| {
+                    e.local_load(body).call();
+                },
+            );
+        }
+        _ => return None,
+    }
+    Some(e.finish())
+}
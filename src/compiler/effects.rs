@@ -0,0 +1,427 @@
+use crate::{
+    compiler::{builtins::find_builtin, control::Control, qualified, CompileError},
+    lang::{
+        ast::{Ast, Definition, Expr, ExprKind, Segment},
+        span::Span,
+    },
+};
+use derive_more::Display;
+use std::collections::{HashMap, HashSet};
+
+/// How many values a word takes from the stack, and how many it leaves
+#[derive(Debug, Display, Clone, Copy, PartialEq, Eq)]
+#[display(fmt = "( {} -- {} )", inputs, outputs)]
+pub struct Effect {
+    pub inputs: usize,
+    pub outputs: usize,
+}
+
+impl Effect {
+    pub const fn new(inputs: usize, outputs: usize) -> Self {
+        Self { inputs, outputs }
+    }
+
+    /// Leaves as many values as it takes
+    pub fn is_balanced(&self) -> bool {
+        self.inputs == self.outputs
+    }
+
+    /// The effect of running one of two words, if both change the depth of
+    /// the stack the same way
+    pub fn either(self, other: Effect) -> Option<Effect> {
+        let inputs = self.inputs.max(other.inputs);
+        let outputs = inputs - self.inputs + self.outputs;
+        (outputs == inputs - other.inputs + other.outputs).then_some(Effect::new(inputs, outputs))
+    }
+}
+
+const PUSH: Effect = Effect::new(0, 1);
+const INTO_STR: Effect = Effect::new(1, 1);
+const CONCAT: Effect = Effect::new(2, 1);
+const LIST_PUSH: Effect = Effect::new(2, 1);
+const TABLE_SET: Effect = Effect::new(3, 1);
+
+/// The stack while going through a body. Values taken from below the body
+/// are its inputs, up to `limit` for bodies that declare an effect
+struct Stack {
+    depth: usize,
+    inputs: usize,
+    limit: Option<usize>,
+}
+
+impl Stack {
+    fn apply(&mut self, effect: Effect, word: &str, span: Span) -> Result<(), CompileError> {
+        if self.depth < effect.inputs {
+            let missing = effect.inputs - self.depth;
+            if let Some(limit) = self.limit.filter(|limit| self.inputs + missing > *limit) {
+                return Err(CompileError::StackUnderflow {
+                    word: word.to_string(),
+                    takes: effect.inputs,
+                    available: self.depth + limit - self.inputs,
+                    span,
+                });
+            }
+            self.inputs += missing;
+            self.depth = effect.inputs;
+        }
+        self.depth = self.depth - effect.inputs + effect.outputs;
+        Ok(())
+    }
+}
+
+/// Infers the stack effect of every definition from the builtins and the
+/// definitions it calls, and checks the bodies of those declaring one as
+/// well as the code building literal values. Bodies calling words of
+/// unknown effect, such as `call`, are not checked past that word
+pub struct Checker<'a> {
+    // Definitions in source order, with the module defining them
+    order: Vec<(&'a str, &'a Definition)>,
+    definitions: HashMap<String, (&'a str, &'a Definition)>,
+    inferred: HashMap<String, Option<Effect>>,
+    // Definitions being inferred, a recursive call has no known effect
+    visiting: HashSet<String>,
+}
+
+impl<'a> Checker<'a> {
+    pub fn new(modules: impl IntoIterator<Item = (&'a str, &'a Ast)>) -> Self {
+        let order: Vec<_> = modules
+            .into_iter()
+            .flat_map(|(module, ast)| ast.definitions.iter().map(move |d| (module, d)))
+            .collect();
+        let definitions = order
+            .iter()
+            .map(|&(module, definition)| {
+                (qualified(module, &definition.name), (module, definition))
+            })
+            .collect();
+        Self {
+            order,
+            definitions,
+            inferred: HashMap::new(),
+            visiting: HashSet::new(),
+        }
+    }
+
+    pub fn check(&mut self) -> Result<(), CompileError> {
+        for (module, definition) in self.order.clone() {
+            self.check_literals(module, &definition.body, &mut vec![])?;
+            let Some(effect) = &definition.effect else {
+                // Inferring the effect checks the control words of the body
+                self.effect(&qualified(module, &definition.name))?;
+                continue;
+            };
+            let declared = Effect::new(effect.inputs.len(), effect.outputs.len());
+            let mut stack = Stack {
+                depth: 0,
+                inputs: 0,
+                limit: Some(declared.inputs),
+            };
+            if !self.apply_body(module, &definition.body, &mut vec![], &mut stack)? {
+                continue;
+            }
+
+            let leaves = declared.inputs - stack.inputs + stack.depth;
+            if leaves != declared.outputs {
+                return Err(CompileError::EffectMismatch {
+                    name: qualified(module, &definition.name),
+                    declared,
+                    leaves,
+                    span: definition.body.last().map_or(effect.span, |expr| expr.span),
+                });
+            }
+        }
+        Ok(())
+    }
+
+    /// Checks that list items, table entries and the code interpolated into
+    /// strings each leave a single value, wherever their effect is known
+    fn check_literals<'e>(
+        &mut self,
+        module: &str,
+        body: &'e [Expr],
+        locals: &mut Vec<&'e str>,
+    ) -> Result<(), CompileError> {
+        for expr in body {
+            match &expr.kind {
+                ExprKind::Bind(names) => locals.extend(names.iter().map(String::as_str)),
+                ExprKind::Closure(body) => {
+                    let outer = locals.len();
+                    self.check_literals(module, body, locals)?;
+                    locals.truncate(outer);
+                }
+                ExprKind::List(items) => {
+                    for item in items {
+                        let item = std::slice::from_ref(item);
+                        self.check_value("List items", module, item, expr.span, locals)?;
+                        self.check_literals(module, item, locals)?;
+                    }
+                }
+                ExprKind::Table(entries) => {
+                    for entry in entries {
+                        let value = &entry.value;
+                        self.check_value("Table entries", module, value, expr.span, locals)?;
+                        self.check_literals(module, value, locals)?;
+                    }
+                }
+                ExprKind::Interpolated(segments) => {
+                    for segment in segments {
+                        if let Segment::Code(code) = segment {
+                            let literal = "Interpolated code";
+                            self.check_value(literal, module, code, expr.span, locals)?;
+                            self.check_literals(module, code, locals)?;
+                        }
+                    }
+                }
+                ExprKind::Int(_)
+                | ExprKind::Float(_)
+                | ExprKind::Char(_)
+                | ExprKind::Str(_)
+                | ExprKind::Term(_)
+                | ExprKind::Quote(_) => {}
+            }
+        }
+        Ok(())
+    }
+
+    /// Fails if the code has a known effect other than pushing one value,
+    /// pointing at the code or at the literal when it is empty
+    fn check_value<'e>(
+        &mut self,
+        literal: &'static str,
+        module: &str,
+        code: &'e [Expr],
+        span: Span,
+        locals: &[&'e str],
+    ) -> Result<(), CompileError> {
+        match self.infer(module, code, locals)? {
+            Some(effect) if effect != PUSH => Err(CompileError::ValueEffect {
+                literal,
+                effect,
+                span: code
+                    .first()
+                    .zip(code.last())
+                    .map_or(span, |(first, last)| first.span.to(last.span)),
+            }),
+            _ => Ok(()),
+        }
+    }
+
+    /// The effect of a definition, the declared one if any
+    pub fn effect(&mut self, name: &str) -> Result<Option<Effect>, CompileError> {
+        let Some(&(module, definition)) = self.definitions.get(name) else {
+            return Ok(None);
+        };
+        if let Some(effect) = &definition.effect {
+            return Ok(Some(Effect::new(effect.inputs.len(), effect.outputs.len())));
+        }
+        if let Some(effect) = self.inferred.get(name) {
+            return Ok(*effect);
+        }
+        if !self.visiting.insert(name.to_string()) {
+            return Ok(None);
+        }
+
+        let mut stack = Stack {
+            depth: 0,
+            inputs: 0,
+            limit: None,
+        };
+        let effect = self
+            .apply_body(module, &definition.body, &mut vec![], &mut stack)?
+            .then_some(Effect::new(stack.inputs, stack.depth));
+        self.visiting.remove(name);
+        self.inferred.insert(name.to_string(), effect);
+        Ok(effect)
+    }
+
+    /// Applies the words of a body in turn, returns false if one of them has
+    /// no known effect
+    fn apply_body<'e>(
+        &mut self,
+        module: &str,
+        body: &'e [Expr],
+        locals: &mut Vec<&'e str>,
+        stack: &mut Stack,
+    ) -> Result<bool, CompileError> {
+        let mut idx = 0;
+        while idx < body.len() {
+            let shadowed = |word: &str| {
+                locals.contains(&word) || self.definitions.contains_key(&self.resolve(module, word))
+            };
+            let known = match Control::find(&body[idx..], shadowed) {
+                Some((control, exprs)) => {
+                    idx += exprs.len();
+                    let span = exprs[exprs.len() - 1].span;
+                    match self.control_effect(module, control, locals, span)? {
+                        Some(effect) => {
+                            stack.apply(effect, control.word(), span)?;
+                            true
+                        }
+                        None => false,
+                    }
+                }
+                None => {
+                    idx += 1;
+                    self.apply_expr(module, &body[idx - 1], locals, stack)?
+                }
+            };
+            if !known {
+                return Ok(false);
+            }
+        }
+        Ok(true)
+    }
+
+    /// The effect of a body on its own, sharing the given locals
+    fn infer<'e>(
+        &mut self,
+        module: &str,
+        body: &'e [Expr],
+        locals: &[&'e str],
+    ) -> Result<Option<Effect>, CompileError> {
+        let mut stack = Stack {
+            depth: 0,
+            inputs: 0,
+            limit: None,
+        };
+        let known = self.apply_body(module, body, &mut locals.to_vec(), &mut stack)?;
+        Ok(known.then_some(Effect::new(stack.inputs, stack.depth)))
+    }
+
+    /// The effect of a control word, known when its quotations are. The
+    /// branches of an `if` must change the depth of the stack the same way
+    fn control_effect<'e>(
+        &mut self,
+        module: &str,
+        control: Control<'e>,
+        locals: &[&'e str],
+        span: Span,
+    ) -> Result<Option<Effect>, CompileError> {
+        let effect = match control {
+            Control::If { then, otherwise } => {
+                let then = self.infer(module, then, locals)?;
+                let otherwise = self.infer(module, otherwise, locals)?;
+                match then.zip(otherwise) {
+                    Some((then, otherwise)) => {
+                        let effect =
+                            then.either(otherwise)
+                                .ok_or(CompileError::UnbalancedBranches {
+                                    then,
+                                    otherwise,
+                                    span,
+                                })?;
+                        Some(Effect::new(effect.inputs + 1, effect.outputs))
+                    }
+                    None => None,
+                }
+            }
+            Control::Times { body } => self
+                .infer(module, body, locals)?
+                .filter(Effect::is_balanced)
+                .map(|effect| Effect::new(effect.inputs + 1, effect.outputs)),
+            Control::While { cond, body } => {
+                let cond = self
+                    .infer(module, cond, locals)?
+                    .filter(|cond| cond.outputs == cond.inputs + 1);
+                let body = self
+                    .infer(module, body, locals)?
+                    .filter(Effect::is_balanced);
+                cond.zip(body).map(|(cond, body)| {
+                    let depth = cond.inputs.max(body.inputs);
+                    Effect::new(depth, depth)
+                })
+            }
+        };
+        Ok(effect)
+    }
+
+    /// Qualified name of a definition called from a module, the names it
+    /// defines shadow the qualified names of other modules
+    fn resolve(&self, module: &str, name: &str) -> String {
+        let local = qualified(module, name);
+        if self.definitions.contains_key(&local) {
+            local
+        } else {
+            name.to_string()
+        }
+    }
+
+    fn apply_expr<'e>(
+        &mut self,
+        module: &str,
+        expr: &'e Expr,
+        locals: &mut Vec<&'e str>,
+        stack: &mut Stack,
+    ) -> Result<bool, CompileError> {
+        match &expr.kind {
+            ExprKind::Term(name) => {
+                let effect = if locals.contains(&name.as_str()) {
+                    Some(PUSH)
+                } else if let Some(builtin) = find_builtin(name) {
+                    builtin.effect
+                } else {
+                    self.effect(&self.resolve(module, name))?
+                };
+                match effect {
+                    Some(effect) => stack.apply(effect, name, expr.span)?,
+                    None => return Ok(false),
+                }
+            }
+            ExprKind::Bind(names) => {
+                let word = format!("-> {}", names.join(" "));
+                stack.apply(Effect::new(names.len(), 0), &word, expr.span)?;
+                locals.extend(names.iter().map(String::as_str));
+            }
+            ExprKind::Interpolated(segments) => {
+                let word = "string interpolation";
+                stack.apply(PUSH, word, expr.span)?;
+                for segment in segments {
+                    match segment {
+                        Segment::Str(_) => stack.apply(PUSH, word, expr.span)?,
+                        Segment::Code(body) => {
+                            if !self.apply_body(module, body, locals, stack)? {
+                                return Ok(false);
+                            }
+                            stack.apply(INTO_STR, word, expr.span)?;
+                        }
+                    }
+                    stack.apply(CONCAT, word, expr.span)?;
+                }
+            }
+            ExprKind::List(items) => {
+                let word = "list literal";
+                stack.apply(PUSH, word, expr.span)?;
+                for item in items {
+                    if !self.apply_expr(module, item, locals, stack)? {
+                        return Ok(false);
+                    }
+                    stack.apply(LIST_PUSH, word, expr.span)?;
+                }
+            }
+            ExprKind::Table(entries) => {
+                let word = "table literal";
+                stack.apply(PUSH, word, expr.span)?;
+                for entry in entries {
+                    stack.apply(PUSH, word, expr.span)?;
+                    if !self.apply_body(module, &entry.value, locals, stack)? {
+                        return Ok(false);
+                    }
+                    stack.apply(TABLE_SET, word, expr.span)?;
+                }
+            }
+            ExprKind::Int(_)
+            | ExprKind::Float(_)
+            | ExprKind::Char(_)
+            | ExprKind::Str(_)
+            | ExprKind::Quote(_)
+            | ExprKind::Closure(_) => stack.apply(PUSH, "literal", expr.span)?,
+        }
+        Ok(true)
+    }
+}
+
+pub fn check<'a>(
+    modules: impl IntoIterator<Item = (&'a str, &'a Ast)>,
+) -> Result<(), CompileError> {
+    Checker::new(modules).check()
+}
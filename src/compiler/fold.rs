@@ -0,0 +1,306 @@
+use crate::{
+    compiler::{builtins::find_builtin, control::Control, CompileError},
+    lang::ast::{Entry, Expr, ExprKind, Segment},
+    vm::value::{MetaValue, Value},
+};
+use eq_float::F64;
+
+/// Folds a definition body ahead of compilation: builtins applied to
+/// constant operands are evaluated, and the branches of control words that
+/// can't run are removed. Errors the pass can prove, such as a constant
+/// division by zero in code certain to run, are reported at the word
+/// raising them.
+/// `defined` tells the names of definitions, which shadow control words
+pub fn fold(body: &[Expr], defined: impl Fn(&str) -> bool) -> Result<Vec<Expr>, CompileError> {
+    Folder {
+        defined: &defined,
+        locals: vec![],
+        certain: true,
+    }
+    .fold_body(body)
+}
+
+struct Folder<'a> {
+    defined: &'a dyn Fn(&str) -> bool,
+    // Names bound so far, they shadow the builtins
+    locals: Vec<String>,
+    // Whether the code being folded runs whenever the definition does,
+    // errors are only reported there
+    certain: bool,
+}
+
+impl Folder<'_> {
+    fn fold_body(&mut self, body: &[Expr]) -> Result<Vec<Expr>, CompileError> {
+        let mut out = vec![];
+        let mut idx = 0;
+        while idx < body.len() {
+            let shadowed = |word: &str| self.is_local(word) || (self.defined)(word);
+            match Control::find(&body[idx..], shadowed) {
+                Some((control, exprs)) => {
+                    self.fold_control(&mut out, control, exprs)?;
+                    idx += exprs.len();
+                }
+                None => {
+                    let expr = self.fold_expr(&body[idx])?;
+                    self.push(&mut out, expr)?;
+                    idx += 1;
+                }
+            }
+        }
+        Ok(out)
+    }
+
+    /// Folds an inlined quotation, locals it binds don't outlive it
+    fn fold_block(&mut self, body: &[Expr]) -> Result<Vec<Expr>, CompileError> {
+        let locals = self.locals.len();
+        let body = self.fold_body(body);
+        self.locals.truncate(locals);
+        body
+    }
+
+    /// Folds a quotation that may not run
+    fn fold_uncertain(&mut self, body: &[Expr]) -> Result<Vec<Expr>, CompileError> {
+        let certain = std::mem::replace(&mut self.certain, false);
+        let body = self.fold_block(body);
+        self.certain = certain;
+        body
+    }
+
+    fn fold_expr(&mut self, expr: &Expr) -> Result<Expr, CompileError> {
+        let kind = match &expr.kind {
+            ExprKind::Bind(names) => {
+                self.locals.extend(names.iter().cloned());
+                expr.kind.clone()
+            }
+            ExprKind::Closure(body) => ExprKind::Closure(self.fold_uncertain(body)?),
+            ExprKind::List(items) => ExprKind::List(
+                items
+                    .iter()
+                    .map(|item| self.fold_expr(item))
+                    .collect::<Result<_, _>>()?,
+            ),
+            ExprKind::Table(entries) => ExprKind::Table(
+                entries
+                    .iter()
+                    .map(|entry| {
+                        Ok(Entry {
+                            key: entry.key.clone(),
+                            value: self.fold_body(&entry.value)?,
+                        })
+                    })
+                    .collect::<Result<_, _>>()?,
+            ),
+            ExprKind::Interpolated(segments) => self.fold_interpolated(segments)?,
+            _ => expr.kind.clone(),
+        };
+        Ok(Expr { kind, ..*expr })
+    }
+
+    /// Code segments folding to a constant are formatted into the string
+    fn fold_interpolated(&mut self, segments: &[Segment]) -> Result<ExprKind, CompileError> {
+        let mut folded: Vec<Segment> = vec![];
+        for segment in segments {
+            let segment = match segment {
+                Segment::Str(s) => Segment::Str(s.clone()),
+                Segment::Code(body) => {
+                    let body = self.fold_body(body)?;
+                    match body.as_slice() {
+                        [expr] => match self.value(expr) {
+                            Some(value) => Segment::Str(MetaValue::new(value).format()),
+                            None => Segment::Code(body),
+                        },
+                        _ => Segment::Code(body),
+                    }
+                }
+            };
+            match (folded.last_mut(), segment) {
+                (Some(Segment::Str(last)), Segment::Str(s)) => last.push_str(&s),
+                (_, segment) => folded.push(segment),
+            }
+        }
+        Ok(match folded.as_slice() {
+            [] => ExprKind::Str(String::new()),
+            [Segment::Str(s)] => ExprKind::Str(s.clone()),
+            _ => ExprKind::Interpolated(folded),
+        })
+    }
+
+    /// Pushes a folded expression, evaluating builtins whose operands are
+    /// all constants
+    fn push(&self, out: &mut Vec<Expr>, expr: Expr) -> Result<(), CompileError> {
+        let ExprKind::Term(word) = &expr.kind else {
+            out.push(expr);
+            return Ok(());
+        };
+        let effect = find_builtin(word)
+            .filter(|_| !self.is_local(word))
+            .and_then(|builtin| builtin.effect);
+        let Some(effect) = effect.filter(|effect| effect.inputs <= out.len()) else {
+            out.push(expr);
+            return Ok(());
+        };
+
+        let operands = &out[out.len() - effect.inputs..];
+        if self.certain
+            && matches!(word.as_str(), "/" | "%")
+            && matches!(
+                operands.last().and_then(|e| self.value(e)),
+                Some(Value::Int(0))
+            )
+        {
+            return Err(CompileError::DivisionByZero(expr.span));
+        }
+        let values: Option<Vec<Value>> = operands.iter().map(|e| self.value(e)).collect();
+        let Some(results) = values.and_then(|values| evaluate(word, &values)) else {
+            out.push(expr);
+            return Ok(());
+        };
+        let Some(kinds) = results
+            .into_iter()
+            .map(|v| self.literal(v))
+            .collect::<Option<Vec<_>>>()
+        else {
+            out.push(expr);
+            return Ok(());
+        };
+
+        let span = operands
+            .first()
+            .map_or(expr.span, |first| first.span.to(expr.span));
+        out.truncate(out.len() - effect.inputs);
+        out.extend(kinds.into_iter().map(|kind| Expr {
+            id: expr.id,
+            span,
+            kind,
+        }));
+        Ok(())
+    }
+
+    /// Inlines the branch taken on a constant condition, and removes loops
+    /// which never run
+    fn fold_control(
+        &mut self,
+        out: &mut Vec<Expr>,
+        control: Control,
+        exprs: &[Expr],
+    ) -> Result<(), CompileError> {
+        let condition = out.last().and_then(|expr| self.value(expr));
+        let quotations = match control {
+            Control::If { then, otherwise } => match condition {
+                // Only the branch taken is folded, errors in the other can't happen
+                Some(Value::Bool(cond)) => {
+                    let taken = self.fold_block(if cond { then } else { otherwise })?;
+                    // Bindings of the branch would outlive it once inlined
+                    if !taken.iter().any(|e| matches!(e.kind, ExprKind::Bind(_))) {
+                        out.pop();
+                        for expr in taken {
+                            self.push(out, expr)?;
+                        }
+                        return Ok(());
+                    }
+                    if cond {
+                        vec![taken, vec![]]
+                    } else {
+                        vec![vec![], taken]
+                    }
+                }
+                _ => vec![self.fold_uncertain(then)?, self.fold_uncertain(otherwise)?],
+            },
+            Control::Times { body } => match condition {
+                Some(Value::Int(count)) if count <= 0 => {
+                    out.pop();
+                    return Ok(());
+                }
+                Some(Value::Int(_)) => vec![self.fold_block(body)?],
+                _ => vec![self.fold_uncertain(body)?],
+            },
+            Control::While { cond, body } => {
+                let cond = self.fold_block(cond)?;
+                if let [expr] = cond.as_slice() {
+                    if self.value(expr) == Some(Value::Bool(false)) {
+                        return Ok(());
+                    }
+                }
+                vec![cond, self.fold_uncertain(body)?]
+            }
+        };
+
+        for (expr, body) in exprs.iter().zip(quotations) {
+            out.push(Expr {
+                kind: ExprKind::Closure(body),
+                ..*expr
+            });
+        }
+        out.push(exprs[exprs.len() - 1].clone());
+        Ok(())
+    }
+
+    fn is_local(&self, word: &str) -> bool {
+        self.locals.iter().any(|local| local == word)
+    }
+
+    /// The value of a constant expression
+    fn value(&self, expr: &Expr) -> Option<Value> {
+        match &expr.kind {
+            ExprKind::Int(v) => Some(Value::Int(*v)),
+            ExprKind::Float(v) => Some(Value::Float(F64(*v))),
+            ExprKind::Str(v) => Some(Value::Str(v.clone())),
+            ExprKind::Term(word) if !self.is_local(word) => match word.as_str() {
+                "true" => Some(Value::Bool(true)),
+                "false" => Some(Value::Bool(false)),
+                _ => None,
+            },
+            _ => None,
+        }
+    }
+
+    /// The expression pushing a value, if it has one
+    fn literal(&self, value: Value) -> Option<ExprKind> {
+        match value {
+            Value::Int(v) => Some(ExprKind::Int(v)),
+            Value::Float(v) => Some(ExprKind::Float(v.0)),
+            Value::Str(v) => Some(ExprKind::Str(v)),
+            Value::Bool(v) => {
+                let word = v.to_string();
+                (!self.is_local(&word)).then_some(ExprKind::Term(word))
+            }
+            Value::List(_) | Value::Table(_) | Value::FunctionRef(_) => None,
+        }
+    }
+}
+
+/// Applies a pure builtin the way the VM would, None if it isn't one or if
+/// the VM would fail on these operands
+fn evaluate(word: &str, operands: &[Value]) -> Option<Vec<Value>> {
+    use Value::{Bool, Float, Int, Str};
+
+    let value = match (word, operands) {
+        ("dup", [a]) => return Some(vec![a.clone(), a.clone()]),
+        ("drop", [_]) => return Some(vec![]),
+        ("swap", [a, b]) => return Some(vec![b.clone(), a.clone()]),
+        ("str", [a]) => Str(MetaValue::new(a.clone()).format()),
+        ("concat", [Str(a), Str(b)]) => Str(format!("{}{}", a, b)),
+        ("and", [Bool(a), Bool(b)]) => Bool(*a && *b),
+        ("or", [Bool(a), Bool(b)]) => Bool(*a || *b),
+        ("xor", [Bool(a), Bool(b)]) => Bool(a ^ b),
+        ("not", [Bool(a)]) => Bool(!a),
+        ("+", [Int(a), Int(b)]) => Int(a.checked_add(*b)?),
+        ("-", [Int(a), Int(b)]) => Int(a.checked_sub(*b)?),
+        ("*", [Int(a), Int(b)]) => Int(a.checked_mul(*b)?),
+        ("/", [Int(a), Int(b)]) => Int(a.checked_div(*b)?),
+        ("%", [Int(a), Int(b)]) => Int(a.checked_rem(*b)?),
+        ("+", [Float(a), Float(b)]) => Float(F64(a.0 + b.0)),
+        ("-", [Float(a), Float(b)]) => Float(F64(a.0 - b.0)),
+        ("*", [Float(a), Float(b)]) => Float(F64(a.0 * b.0)),
+        ("/", [Float(a), Float(b)]) => Float(F64(a.0 / b.0)),
+        ("%", [Float(a), Float(b)]) => Float(F64(a.0 % b.0)),
+        ("=", [a, b]) => Bool(a == b),
+        ("!=", [a, b]) => Bool(a != b),
+        ("<", [Int(a), Int(b)]) => Bool(a < b),
+        (">", [Int(a), Int(b)]) => Bool(a > b),
+        ("<=", [Int(a), Int(b)]) => Bool(a <= b),
+        (">=", [Int(a), Int(b)]) => Bool(a >= b),
+        _ => return None,
+    };
+    Some(vec![value])
+}
@@ -0,0 +1,513 @@
+use crate::{
+    compiler::{
+        builtins::Builtin,
+        control::{runtime_function, Control},
+        effects::Effect,
+        resolve::{Symbol, SymbolTable},
+        scope::Scope,
+    },
+    lang::{
+        ast::{Ast, Definition, Entry, Expr, ExprKind, Segment},
+        diagnostic::Diagnostic,
+        loader::Program,
+        span::Span,
+    },
+    vm::{
+        emitter::Emitter,
+        function::{Function, Functions},
+        instructions::Inst,
+        value::{MetaValue, Table},
+    },
+};
+use std::{collections::HashSet, fmt::Display};
+use thiserror::Error;
+
+pub mod builtins;
+pub mod control;
+pub mod effects;
+pub mod fold;
+pub mod resolve;
+mod scope;
+
+#[derive(Debug, Error, PartialEq)]
+pub enum CompileError {
+    #[error("Duplicate definition '{0}'")]
+    DuplicateDefinition(String),
+    #[error("{0} are not supported yet")]
+    Unsupported(String),
+    #[error("Unknown word '{word}'")]
+    UnknownWord {
+        word: String,
+        suggestion: Option<String>,
+        span: Span,
+    },
+    #[error("'{name}' is a builtin and can't be redefined")]
+    BuiltinRedefinition { name: String, span: Span },
+    #[error("Division by zero")]
+    DivisionByZero(Span),
+    #[error("'{word}' takes {takes} values but only {available} are on the stack")]
+    StackUnderflow {
+        word: String,
+        takes: usize,
+        available: usize,
+        span: Span,
+    },
+    #[error("'{name}' leaves {leaves} values but its stack effect declares {}", declared.outputs)]
+    EffectMismatch {
+        name: String,
+        declared: Effect,
+        leaves: usize,
+        span: Span,
+    },
+    #[error("The branches of 'if' change the stack differently, {then} and {otherwise}")]
+    UnbalancedBranches {
+        then: Effect,
+        otherwise: Effect,
+        span: Span,
+    },
+    /// Code building part of a literal value doesn't leave one value
+    #[error("{literal} must leave a single value, but the stack effect is {effect}")]
+    ValueEffect {
+        literal: &'static str,
+        effect: Effect,
+        span: Span,
+    },
+}
+
+impl CompileError {
+    pub fn span(&self) -> Option<Span> {
+        match self {
+            CompileError::UnknownWord { span, .. }
+            | CompileError::BuiltinRedefinition { span, .. }
+            | CompileError::DivisionByZero(span)
+            | CompileError::StackUnderflow { span, .. }
+            | CompileError::EffectMismatch { span, .. }
+            | CompileError::UnbalancedBranches { span, .. }
+            | CompileError::ValueEffect { span, .. } => Some(*span),
+            CompileError::DuplicateDefinition(_) | CompileError::Unsupported(_) => None,
+        }
+    }
+}
+
+impl From<&CompileError> for Diagnostic {
+    fn from(error: &CompileError) -> Self {
+        let diagnostic = Diagnostic::error(error.to_string()).with_span(error.span());
+        match error {
+            CompileError::UnknownWord {
+                suggestion: Some(suggestion),
+                ..
+            } => diagnostic.with_note(format!("did you mean '{}'?", suggestion)),
+            _ => diagnostic,
+        }
+    }
+}
+
+/// State of the function being compiled
+struct Context {
+    name: String,
+    emitter: Emitter,
+    scope: Scope,
+    closures: usize,
+}
+
+impl Context {
+    fn new(name: impl Into<String>, emitter: Emitter, scope: Scope) -> Self {
+        Self {
+            name: name.into(),
+            emitter,
+            scope,
+            closures: 0,
+        }
+    }
+
+    /// Closures are lifted into functions named after their parent
+    fn closure_name(&mut self) -> String {
+        let name = reserved_name(&self.name, self.closures);
+        self.closures += 1;
+        name
+    }
+}
+
+#[derive(Default)]
+pub struct Compiler {
+    functions: Functions,
+    // Module being compiled, and the unqualified names it defines
+    module: String,
+    definitions: HashSet<String>,
+    // Symbols of the whole program, so that calls are resolved before their
+    // callee is compiled
+    symbols: SymbolTable,
+}
+
+impl Compiler {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn compile(mut self, ast: &Ast) -> Result<Functions, CompileError> {
+        self.symbols = resolve::resolve([("", ast)])?;
+        self.compile_module("", ast)?;
+        effects::check([("", ast)])?;
+        Ok(self.functions)
+    }
+
+    pub fn compile_program(mut self, program: &Program) -> Result<Functions, CompileError> {
+        let modules = || {
+            program
+                .modules
+                .iter()
+                .map(|module| (module.name.as_str(), &module.ast))
+        };
+        self.symbols = resolve::resolve(modules())?;
+        for (name, ast) in modules() {
+            self.compile_module(name, ast)?;
+        }
+        effects::check(modules())?;
+        Ok(self.functions)
+    }
+
+    fn compile_module(&mut self, module: &str, ast: &Ast) -> Result<(), CompileError> {
+        self.module = module.to_string();
+        self.definitions.clear();
+        for definition in &ast.definitions {
+            if !self.definitions.insert(definition.name.clone()) {
+                return Err(CompileError::DuplicateDefinition(
+                    self.qualify(&definition.name),
+                ));
+            }
+        }
+
+        for definition in &ast.definitions {
+            let function = self.compile_definition(definition)?;
+            self.functions
+                .insert(self.qualify(&definition.name), function);
+        }
+        Ok(())
+    }
+
+    /// Names defined by the current module are namespaced by the module name
+    fn qualify(&self, name: &str) -> String {
+        if self.definitions.contains(name) {
+            qualified(&self.module, name)
+        } else {
+            name.to_string()
+        }
+    }
+
+    fn compile_definition(&mut self, definition: &Definition) -> Result<Function, CompileError> {
+        let name = self.qualify(&definition.name);
+        let body = fold::fold(&definition.body, |word| self.is_definition(word))?;
+        let mut ctx = Context::new(name, Emitter::new(), Scope::new());
+        self.compile_body(&mut ctx, &body)?;
+        Ok(ctx.emitter.finish())
+    }
+
+    fn compile_body(&mut self, ctx: &mut Context, body: &[Expr]) -> Result<(), CompileError> {
+        let mut idx = 0;
+        while idx < body.len() {
+            let shadowed = |word: &str| self.is_shadowed(ctx, word);
+            match Control::find(&body[idx..], shadowed) {
+                Some((control, exprs)) => {
+                    let word = &exprs[exprs.len() - 1];
+                    self.compile_control(ctx, control, word.span)?;
+                    idx += exprs.len();
+                }
+                None => {
+                    self.compile_expr(ctx, &body[idx])?;
+                    idx += 1;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Words of the language can be shadowed by locals and definitions
+    fn is_shadowed(&self, ctx: &Context, word: &str) -> bool {
+        ctx.scope.get(word).is_some() || self.is_definition(word)
+    }
+
+    /// The symbol a word was resolved to, locals aside
+    fn symbol(&self, word: &str, span: Span) -> Result<Symbol, CompileError> {
+        self.symbols
+            .lookup(&self.module, word)
+            .ok_or_else(|| CompileError::UnknownWord {
+                word: word.to_string(),
+                suggestion: None,
+                span,
+            })
+    }
+
+    fn is_definition(&self, word: &str) -> bool {
+        matches!(
+            self.symbols.lookup(&self.module, word),
+            Some(Symbol::Definition(_))
+        )
+    }
+
+    /// Inlines a control word applied to literal quotations
+    fn compile_control(
+        &mut self,
+        ctx: &mut Context,
+        control: Control,
+        span: Span,
+    ) -> Result<(), CompileError> {
+        match control {
+            Control::If { then, otherwise } => {
+                ctx.emitter.at(span).nop();
+                let branch = ctx.emitter.previous_idx();
+                self.compile_block(ctx, then)?;
+                ctx.emitter.at(span).nop();
+                let skip = ctx.emitter.previous_idx();
+
+                let else_target = ctx.emitter.current_idx();
+                ctx.emitter.patch(branch, Inst::BranchIfNot(else_target));
+                self.compile_block(ctx, otherwise)?;
+                let end = ctx.emitter.current_idx();
+                ctx.emitter.patch(skip, Inst::Branch(end));
+            }
+            Control::Times { body } => {
+                let count = ctx.emitter.local_new();
+                ctx.emitter.at(span).local_store(count);
+                let outer = ctx.emitter.begin_loop();
+                ctx.emitter
+                    .local_load(count)
+                    .push_int(0)
+                    .greater_than()
+                    .break_if_not();
+                self.compile_block(ctx, body)?;
+                ctx.emitter
+                    .at(span)
+                    .local_load(count)
+                    .push_int(1)
+                    .sub()
+                    .local_store(count)
+                    .end_loop(outer);
+            }
+            Control::While { cond, body } => {
+                let outer = ctx.emitter.begin_loop();
+                self.compile_block(ctx, cond)?;
+                ctx.emitter.at(span).break_if_not();
+                self.compile_block(ctx, body)?;
+                ctx.emitter.at(span).end_loop(outer);
+            }
+        }
+        Ok(())
+    }
+
+    /// Compiles an inlined quotation, locals it binds don't outlive it
+    fn compile_block(&mut self, ctx: &mut Context, body: &[Expr]) -> Result<(), CompileError> {
+        let scope = ctx.scope.clone();
+        let result = self.compile_body(ctx, body);
+        ctx.scope = scope;
+        result
+    }
+
+    fn compile_expr(&mut self, ctx: &mut Context, expr: &Expr) -> Result<(), CompileError> {
+        let e = ctx.emitter.at(expr.span);
+        match &expr.kind {
+            ExprKind::Int(v) => {
+                e.push_int(*v);
+            }
+            ExprKind::Float(v) => {
+                e.push_floatt(*v);
+            }
+            ExprKind::Term(name) => match ctx.scope.get(name) {
+                Some(local) => {
+                    e.local_load(local);
+                }
+                None => match self.symbol(name, expr.span)? {
+                    Symbol::Builtin(builtin) => {
+                        ctx.emitter.emit(builtin.inst.clone());
+                    }
+                    Symbol::Definition(function) => {
+                        ctx.emitter.push_function_ref(function).call();
+                    }
+                    Symbol::Control(word) => {
+                        let function = self.control_wrapper(word);
+                        ctx.emitter.push_function_ref(function).call();
+                    }
+                },
+            },
+            ExprKind::Quote(name) => match self.symbol(name, expr.span)? {
+                Symbol::Builtin(builtin) => {
+                    let function = self.builtin_wrapper(builtin);
+                    ctx.emitter.push_function_ref(function);
+                }
+                Symbol::Definition(function) => {
+                    ctx.emitter.push_function_ref(function);
+                }
+                Symbol::Control(word) => {
+                    let function = self.control_wrapper(word);
+                    ctx.emitter.push_function_ref(function);
+                }
+            },
+            ExprKind::Closure(body) => self.compile_closure(ctx, body)?,
+            ExprKind::List(items) => match constant(expr) {
+                Some(value) => {
+                    e.push_value(value);
+                }
+                None => {
+                    e.push_list();
+                    for item in items {
+                        self.compile_expr(ctx, item)?;
+                        ctx.emitter.at(expr.span).list_push();
+                    }
+                }
+            },
+            ExprKind::Table(entries) => match constant(expr) {
+                Some(value) => {
+                    e.push_value(value);
+                }
+                None => {
+                    e.push_table();
+                    for entry in entries {
+                        ctx.emitter.at(expr.span).push_str(&entry.key);
+                        self.compile_body(ctx, &entry.value)?;
+                        ctx.emitter.at(expr.span).table_set();
+                    }
+                }
+            },
+            ExprKind::Bind(names) => {
+                // The last name takes the top of the stack, so it is stored first
+                let locals: Vec<usize> = names.iter().map(|_| e.local_new()).collect();
+                for local in locals.iter().rev() {
+                    e.local_store(*local);
+                }
+                for (name, local) in names.iter().zip(locals) {
+                    ctx.scope.declare(name, local);
+                }
+            }
+            ExprKind::Str(v) => {
+                e.push_str(v);
+            }
+            ExprKind::Interpolated(segments) => self.compile_interpolated(ctx, expr, segments)?,
+            ExprKind::Char(_) => return unsupported("Char literals"),
+        }
+        Ok(())
+    }
+
+    /// Concatenates the segments, values of interpolated code are formatted
+    /// into strings
+    fn compile_interpolated(
+        &mut self,
+        ctx: &mut Context,
+        expr: &Expr,
+        segments: &[Segment],
+    ) -> Result<(), CompileError> {
+        ctx.emitter.push_str("");
+        for segment in segments {
+            match segment {
+                Segment::Str(s) => {
+                    ctx.emitter.push_str(s);
+                }
+                Segment::Code(body) => {
+                    self.compile_body(ctx, body)?;
+                    ctx.emitter.at(expr.span).into_str();
+                }
+            }
+            ctx.emitter.concat();
+        }
+        Ok(())
+    }
+
+    /// Builtins have no function of their own, a quoted builtin refers to a
+    /// wrapper function, returns its name
+    fn builtin_wrapper(&mut self, builtin: &Builtin) -> String {
+        let name = reserved_name("", builtin.name);
+        self.functions.entry(name.clone()).or_insert_with(|| {
+            let mut e = Emitter::new();
+            e.emit(builtin.inst.clone());
+            e.finish()
+        });
+        name
+    }
+
+    /// Control words given quotations that aren't literal are run by a
+    /// function of their own, returns its name
+    fn control_wrapper(&mut self, word: &str) -> String {
+        let name = reserved_name("", word);
+        if let Some(function) = runtime_function(word) {
+            self.functions.entry(name.clone()).or_insert(function);
+        }
+        name
+    }
+
+    /// Lifts the closure body into its own function, and binds the locals it
+    /// captures into its environment at the creation site
+    fn compile_closure(&mut self, ctx: &mut Context, body: &[Expr]) -> Result<(), CompileError> {
+        let name = ctx.closure_name();
+        let captures = ctx.scope.captures(body);
+
+        let mut closure = Context::new(
+            &name,
+            Emitter::with_env(captures.len()),
+            Scope::with_env(&captures),
+        );
+        self.compile_body(&mut closure, body)?;
+        self.functions
+            .insert(name.clone(), closure.emitter.finish());
+
+        let e = &mut ctx.emitter;
+        e.push_function_ref(name);
+        if !captures.is_empty() {
+            e.push_list();
+            for capture in &captures {
+                let local = ctx.scope.get(capture).unwrap_or_default();
+                e.local_load(local).list_push();
+            }
+            e.bind();
+        }
+        Ok(())
+    }
+}
+
+pub fn compile(ast: &Ast) -> Result<Functions, CompileError> {
+    Compiler::new().compile(ast)
+}
+
+pub fn compile_program(program: &Program) -> Result<Functions, CompileError> {
+    Compiler::new().compile_program(program)
+}
+
+/// The value of a literal made of constants only, lists and tables of such
+/// literals are built at compile time
+fn constant(expr: &Expr) -> Option<MetaValue> {
+    match &expr.kind {
+        ExprKind::Int(v) => Some(MetaValue::int(*v)),
+        ExprKind::Float(v) => Some(MetaValue::float(*v)),
+        ExprKind::Str(v) => Some(MetaValue::str(v)),
+        ExprKind::List(items) => items
+            .iter()
+            .map(constant)
+            .collect::<Option<_>>()
+            .map(MetaValue::list),
+        ExprKind::Table(entries) => entries
+            .iter()
+            .map(|Entry { key, value }| match value.as_slice() {
+                [value] => Some((MetaValue::str(key), constant(value)?)),
+                _ => None,
+            })
+            .collect::<Option<Table>>()
+            .map(MetaValue::table),
+        _ => None,
+    }
+}
+
+fn qualified(module: &str, name: &str) -> String {
+    if module.is_empty() {
+        name.to_string()
+    } else {
+        format!("{}.{}", module, name)
+    }
+}
+
+/// Name of a function generated for `owner`, or for the whole program when
+/// it is empty, such as a lifted closure or the function running a control
+/// word. Terms can't contain a '#', so these never clash with definitions
+pub fn reserved_name(owner: &str, name: impl Display) -> String {
+    format!("{}#{}", owner, name)
+}
+
+fn unsupported(what: impl Into<String>) -> Result<(), CompileError> {
+    Err(CompileError::Unsupported(what.into()))
+}
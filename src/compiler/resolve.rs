@@ -0,0 +1,205 @@
+use crate::{
+    compiler::{
+        builtins::{find_builtin, Builtin, BUILTINS},
+        control, qualified, CompileError,
+    },
+    lang::{
+        ast::{Ast, Expr, ExprKind, Segment},
+        span::Span,
+    },
+};
+use std::collections::{BTreeMap, HashMap};
+
+/// What a word other than a local refers to
+#[derive(Debug, Clone)]
+pub enum Symbol {
+    Builtin(&'static Builtin),
+    /// A definition, by its qualified name
+    Definition(String),
+    /// A control word, run by a function of its own when its quotations
+    /// aren't literal
+    Control(&'static str),
+}
+
+/// The names visible from each module: the builtins, the definitions of the
+/// module, and those of the modules it imports qualified by their module
+#[derive(Debug, Default)]
+pub struct SymbolTable {
+    // Visible names of definitions to their qualified name, per module
+    modules: HashMap<String, BTreeMap<String, String>>,
+}
+
+impl SymbolTable {
+    pub fn new<'a>(modules: impl IntoIterator<Item = (&'a str, &'a Ast)>) -> Self {
+        let modules: Vec<_> = modules.into_iter().collect();
+        let mut table = Self::default();
+        for (module, ast) in &modules {
+            let names = table.modules.entry(module.to_string()).or_default();
+            for definition in &ast.definitions {
+                let name = qualified(module, &definition.name);
+                names.insert(definition.name.clone(), name.clone());
+                names.insert(name.clone(), name);
+            }
+            let imports = modules
+                .iter()
+                .filter(|(name, _)| ast.imports.iter().any(|import| import == name));
+            for (import, imported) in imports {
+                for definition in &imported.definitions {
+                    let name = qualified(import, &definition.name);
+                    names.insert(name.clone(), name);
+                }
+            }
+        }
+        table
+    }
+
+    /// Builtins come first, then definitions, which shadow control words
+    pub fn lookup(&self, module: &str, word: &str) -> Option<Symbol> {
+        if let Some(builtin) = find_builtin(word) {
+            return Some(Symbol::Builtin(builtin));
+        }
+        if let Some(name) = self.modules.get(module).and_then(|names| names.get(word)) {
+            return Some(Symbol::Definition(name.clone()));
+        }
+        control::WORDS
+            .iter()
+            .find(|control| **control == word)
+            .map(|control| Symbol::Control(control))
+    }
+
+    /// Every word visible from a module, locals aside, definitions first
+    pub fn names<'t>(&'t self, module: &str) -> impl Iterator<Item = &'t str> {
+        let definitions = self
+            .modules
+            .get(module)
+            .into_iter()
+            .flat_map(BTreeMap::keys);
+        definitions
+            .map(String::as_str)
+            .chain(BUILTINS.iter().map(|builtin| builtin.name))
+            .chain(control::WORDS.iter().copied())
+    }
+}
+
+/// Resolves every word of the modules to a local or to a symbol, and builds
+/// the table the compiler looks symbols up in. Unknown words are reported
+/// with the closest visible name. Definitions named after a builtin are
+/// rejected, builtins are looked up first so they could never be called
+pub fn resolve<'a>(
+    modules: impl IntoIterator<Item = (&'a str, &'a Ast)>,
+) -> Result<SymbolTable, CompileError> {
+    let modules: Vec<_> = modules.into_iter().collect();
+    let table = SymbolTable::new(modules.iter().copied());
+    for (module, ast) in modules {
+        for definition in &ast.definitions {
+            if find_builtin(&definition.name).is_some() {
+                return Err(CompileError::BuiltinRedefinition {
+                    name: definition.name.clone(),
+                    span: definition.span,
+                });
+            }
+            Resolver {
+                table: &table,
+                module,
+                locals: vec![],
+            }
+            .resolve_body(&definition.body)?;
+        }
+    }
+    Ok(table)
+}
+
+struct Resolver<'a> {
+    table: &'a SymbolTable,
+    module: &'a str,
+    locals: Vec<String>,
+}
+
+impl Resolver<'_> {
+    fn resolve_body(&mut self, body: &[Expr]) -> Result<(), CompileError> {
+        for expr in body {
+            self.resolve_expr(expr)?;
+        }
+        Ok(())
+    }
+
+    fn resolve_expr(&mut self, expr: &Expr) -> Result<(), CompileError> {
+        match &expr.kind {
+            ExprKind::Term(word) => {
+                if !self.locals.contains(word) && self.table.lookup(self.module, word).is_none() {
+                    return Err(self.unknown(word, expr.span, true));
+                }
+            }
+            // Quotes name functions, locals aren't one
+            ExprKind::Quote(word) => {
+                if self.table.lookup(self.module, word).is_none() {
+                    return Err(self.unknown(word, expr.span, false));
+                }
+            }
+            ExprKind::Bind(names) => self.locals.extend(names.iter().cloned()),
+            // Locals bound in a quotation don't outlive it, whether it is
+            // lifted or inlined
+            ExprKind::Closure(body) => {
+                let locals = self.locals.len();
+                self.resolve_body(body)?;
+                self.locals.truncate(locals);
+            }
+            ExprKind::List(items) => self.resolve_body(items)?,
+            ExprKind::Table(entries) => {
+                for entry in entries {
+                    self.resolve_body(&entry.value)?;
+                }
+            }
+            ExprKind::Interpolated(segments) => {
+                for segment in segments {
+                    if let Segment::Code(body) = segment {
+                        self.resolve_body(body)?;
+                    }
+                }
+            }
+            ExprKind::Int(_) | ExprKind::Float(_) | ExprKind::Char(_) | ExprKind::Str(_) => {}
+        }
+        Ok(())
+    }
+
+    fn unknown(&self, word: &str, span: Span, locals: bool) -> CompileError {
+        let locals = self
+            .locals
+            .iter()
+            .rev()
+            .filter(|_| locals)
+            .map(String::as_str);
+        CompileError::UnknownWord {
+            word: word.to_string(),
+            suggestion: suggest(word, locals.chain(self.table.names(self.module))),
+            span,
+        }
+    }
+}
+
+/// The candidate closest to a misspelled word, the first one on ties, if
+/// it is close enough to be a likely typo. Words too short to tell a typo
+/// from another word get no suggestion
+fn suggest<'c>(word: &str, candidates: impl Iterator<Item = &'c str>) -> Option<String> {
+    let limit = word.chars().count() / 3;
+    candidates
+        .map(|candidate| (distance(word, candidate), candidate))
+        .filter(|(distance, _)| (1..=limit).contains(distance))
+        .min_by_key(|(distance, _)| *distance)
+        .map(|(_, candidate)| candidate.to_string())
+}
+
+/// Levenshtein distance, in chars
+fn distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut previous: Vec<usize> = (0..=b.len()).collect();
+    for (i, a) in a.chars().enumerate() {
+        let mut current = vec![i + 1];
+        for (j, b) in b.iter().enumerate() {
+            let substitution = previous[j] + usize::from(a != *b);
+            current.push(substitution.min(previous[j + 1] + 1).min(current[j] + 1));
+        }
+        previous = current;
+    }
+    previous[b.len()]
+}
@@ -0,0 +1,83 @@
+use crate::lang::ast::{Expr, ExprKind, Segment};
+
+/// Named locals visible from the code being compiled
+#[derive(Debug, Default, Clone)]
+pub struct Scope {
+    // Later entries shadow earlier ones
+    locals: Vec<(String, usize)>,
+}
+
+impl Scope {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Scope of a closure, whose captured variables fill the first env slots
+    pub fn with_env(captures: &[String]) -> Self {
+        Self {
+            locals: captures
+                .iter()
+                .cloned()
+                .enumerate()
+                .map(|(idx, name)| (name, idx))
+                .collect(),
+        }
+    }
+
+    /// Names a local, shadowing any other local of that name
+    pub fn declare(&mut self, name: impl Into<String>, local: usize) {
+        self.locals.push((name.into(), local));
+    }
+
+    pub fn get(&self, name: &str) -> Option<usize> {
+        self.locals
+            .iter()
+            .rev()
+            .find(|(local, _)| local == name)
+            .map(|(_, idx)| *idx)
+    }
+
+    /// Names of the locals of this scope used by a closure body, in order of first use
+    pub fn captures(&self, body: &[Expr]) -> Vec<String> {
+        let mut captures = vec![];
+        self.collect_captures(body, &mut vec![], &mut captures);
+        captures
+    }
+
+    /// Names bound within the body shadow the locals of this scope
+    fn collect_captures(&self, body: &[Expr], bound: &mut Vec<String>, captures: &mut Vec<String>) {
+        let outer = bound.len();
+        for expr in body {
+            match &expr.kind {
+                ExprKind::Term(name) => {
+                    if self.get(name).is_some() && !bound.contains(name) && !captures.contains(name)
+                    {
+                        captures.push(name.clone());
+                    }
+                }
+                ExprKind::Bind(names) => bound.extend(names.iter().cloned()),
+                ExprKind::Closure(body) | ExprKind::List(body) => {
+                    self.collect_captures(body, bound, captures)
+                }
+                ExprKind::Table(entries) => {
+                    for entry in entries {
+                        self.collect_captures(&entry.value, bound, captures);
+                    }
+                }
+                ExprKind::Interpolated(segments) => {
+                    for segment in segments {
+                        if let Segment::Code(body) = segment {
+                            self.collect_captures(body, bound, captures);
+                        }
+                    }
+                }
+                ExprKind::Int(_)
+                | ExprKind::Float(_)
+                | ExprKind::Char(_)
+                | ExprKind::Str(_)
+                | ExprKind::Quote(_) => {}
+            }
+        }
+        bound.truncate(outer);
+    }
+}
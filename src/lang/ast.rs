@@ -1,18 +1,70 @@
+use crate::lang::span::Span;
+
+/// Identifies a node of a parsed source. Ids are handed out in source order
+/// so a given source always gets the same ids
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord, Default)]
+pub struct NodeId(pub u32);
+
+#[derive(Debug, Clone, PartialEq)]
 pub struct Ast {
     pub imports: Vec<String>,
     pub definitions: Vec<Definition>,
 }
 
+#[derive(Debug, Clone, PartialEq)]
 pub struct Definition {
+    pub id: NodeId,
+    // From `def` to the end of the body
+    pub span: Span,
     pub name: String,
+    /// The `##` comment lines right above the definition
+    pub doc: Option<String>,
+    pub effect: Option<StackEffect>,
     pub body: Vec<Expr>,
 }
 
-pub enum Expr {
+/// The declared stack effect of a definition, `( a b -- c )` names the
+/// values it takes and the ones it leaves, tops of the stack last
+#[derive(Debug, Clone, PartialEq)]
+pub struct StackEffect {
+    pub span: Span,
+    pub inputs: Vec<String>,
+    pub outputs: Vec<String>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Expr {
+    pub id: NodeId,
+    pub span: Span,
+    pub kind: ExprKind,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ExprKind {
     Int(i64),
     Float(f64),
     Char(char),
     Str(String),
+    Interpolated(Vec<Segment>),
     Term(String),
+    Quote(String),
     Closure(Vec<Expr>),
+    /// `-> a b` pops the top values into locals, `b` being the top one
+    Bind(Vec<String>),
+    /// Each item is an element of the list
+    List(Vec<Expr>),
+    Table(Vec<Entry>),
+}
+
+/// An entry of a table literal, its value is the code up to the next `,`
+#[derive(Debug, Clone, PartialEq)]
+pub struct Entry {
+    pub key: String,
+    pub value: Vec<Expr>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Segment {
+    Str(String),
+    Code(Vec<Expr>),
 }
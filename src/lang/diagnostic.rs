@@ -0,0 +1,120 @@
+use crate::lang::{
+    parser::ParseError,
+    source::{Location, SourceFile, SourceMap},
+    span::Span,
+};
+use derive_more::Display;
+use std::fmt::Write;
+
+#[derive(Debug, Display, Clone, Copy, PartialEq, Eq)]
+pub enum Severity {
+    #[display(fmt = "error")]
+    Error,
+    #[display(fmt = "warning")]
+    Warning,
+}
+
+/// A message about the source, rendered with the offending line and an
+/// underline below the span it points at
+#[derive(Debug, Clone, PartialEq)]
+pub struct Diagnostic {
+    pub severity: Severity,
+    pub message: String,
+    pub span: Option<Span>,
+    pub notes: Vec<String>,
+}
+
+impl Diagnostic {
+    pub fn new(severity: Severity, message: impl Into<String>) -> Self {
+        Self {
+            severity,
+            message: message.into(),
+            span: None,
+            notes: vec![],
+        }
+    }
+
+    pub fn error(message: impl Into<String>) -> Self {
+        Self::new(Severity::Error, message)
+    }
+
+    pub fn warning(message: impl Into<String>) -> Self {
+        Self::new(Severity::Warning, message)
+    }
+
+    pub fn with_span(self, span: Option<Span>) -> Self {
+        Self { span, ..self }
+    }
+
+    pub fn with_note(mut self, note: impl Into<String>) -> Self {
+        self.notes.push(note.into());
+        self
+    }
+
+    /// The file the diagnostic points into, and where
+    pub fn location<'a>(&self, sources: &'a SourceMap) -> Option<(&'a SourceFile, Location)> {
+        sources.location(self.span?)
+    }
+
+    /// Renders the diagnostic like:
+    ///
+    /// ```text
+    /// error: Unexpected char '@'
+    ///  --> main.mana:3:9
+    ///   |
+    /// 3 |     foo @ bar
+    ///   |         ^
+    /// ```
+    pub fn render(&self, sources: &SourceMap) -> String {
+        let mut out = String::new();
+        let _ = writeln!(out, "{}: {}", self.severity, self.message);
+
+        match self.location(sources) {
+            Some((file, location)) => {
+                let gutter = " ".repeat(location.line.to_string().len());
+                let _ = writeln!(
+                    out,
+                    "{}--> {}:{}:{}",
+                    gutter, file.name, location.line, location.column
+                );
+
+                let line = file.line(location.line).unwrap_or_default();
+                let width = line.chars().count();
+                let start = (location.column - 1).min(width);
+                // Spans are in bytes, the underline is in chars
+                let length = self
+                    .span
+                    .and_then(|span| file.source.get(span.start..span.end()))
+                    .map_or(1, |text| text.chars().count())
+                    .min(width - start)
+                    .max(1);
+
+                let _ = writeln!(out, "{} |", gutter);
+                let _ = writeln!(out, "{} | {}", location.line, line);
+                let _ = writeln!(
+                    out,
+                    "{} | {}{}",
+                    gutter,
+                    " ".repeat(start),
+                    "^".repeat(length)
+                );
+                for note in &self.notes {
+                    let _ = writeln!(out, "{} = note: {}", gutter, note);
+                }
+            }
+            None => {
+                for note in &self.notes {
+                    let _ = writeln!(out, "  = note: {}", note);
+                }
+            }
+        }
+
+        out
+    }
+}
+
+impl From<&ParseError> for Diagnostic {
+    fn from(error: &ParseError) -> Self {
+        Diagnostic::error(error.kind().to_string()).with_span(error.span())
+    }
+}
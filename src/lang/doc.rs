@@ -0,0 +1,156 @@
+use crate::lang::{
+    ast::Definition,
+    loader::{Module, Program},
+};
+use std::fmt::Write;
+
+/// Renders a reference of a program as Markdown, the root module first and
+/// then the modules it imports. Each definition is listed with its stack
+/// effect and its `##` doc comment
+pub fn markdown(program: &Program) -> String {
+    let mut out = String::new();
+    for module in modules(program) {
+        let name = module_name(module);
+        let _ = writeln!(out, "<a id=\"{}\"></a>\n\n# {}\n", name, name);
+        if !module.ast.imports.is_empty() {
+            let imports: Vec<String> = module
+                .ast
+                .imports
+                .iter()
+                .map(|import| format!("[{}](#{})", import, import))
+                .collect();
+            let _ = writeln!(out, "Imports: {}\n", imports.join(", "));
+        }
+        for definition in &module.ast.definitions {
+            let _ = writeln!(
+                out,
+                "- [`{}`](#{})",
+                definition.name,
+                anchor(&name, definition)
+            );
+        }
+        out.push('\n');
+
+        for definition in &module.ast.definitions {
+            let _ = writeln!(
+                out,
+                "<a id=\"{}\"></a>\n\n## `{}`\n",
+                anchor(&name, definition),
+                signature(definition)
+            );
+            if let Some(doc) = &definition.doc {
+                let _ = writeln!(out, "{}\n", doc);
+            }
+        }
+    }
+    out
+}
+
+/// Renders the same reference as [`markdown`] as a standalone HTML page,
+/// with an index of every module and definition
+pub fn html(program: &Program) -> String {
+    let mut nav = String::new();
+    let mut body = String::new();
+    for module in modules(program) {
+        let name = module_name(module);
+        let _ = write!(
+            nav,
+            "<li><a href=\"#{}\">{}</a><ul>",
+            escape(&name),
+            escape(&name)
+        );
+        let _ = write!(
+            body,
+            "<section id=\"{}\">\n<h1>{}</h1>\n",
+            escape(&name),
+            escape(&name)
+        );
+        if !module.ast.imports.is_empty() {
+            let imports: Vec<String> = module
+                .ast
+                .imports
+                .iter()
+                .map(|import| format!("<a href=\"#{}\">{}</a>", escape(import), escape(import)))
+                .collect();
+            let _ = writeln!(body, "<p>Imports: {}</p>", imports.join(", "));
+        }
+
+        for definition in &module.ast.definitions {
+            let anchor = escape(&anchor(&name, definition));
+            let _ = write!(
+                nav,
+                "<li><a href=\"#{}\">{}</a></li>",
+                anchor,
+                escape(&definition.name)
+            );
+            let _ = writeln!(
+                body,
+                "<article id=\"{}\">\n<h2><code>{}</code></h2>",
+                anchor,
+                escape(&signature(definition))
+            );
+            // Blank lines of a doc comment separate its paragraphs
+            for paragraph in definition.doc.iter().flat_map(|doc| doc.split("\n\n")) {
+                let _ = writeln!(body, "<p>{}</p>", escape(paragraph.trim()));
+            }
+            body.push_str("</article>\n");
+        }
+        nav.push_str("</ul></li>\n");
+        body.push_str("</section>\n");
+    }
+
+    format!(
+        "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n<title>{}</title>\n</head>\n\
+         <body>\n<nav>\n<ul>\n{}</ul>\n</nav>\n<main>\n{}</main>\n</body>\n</html>\n",
+        modules(program)
+            .next()
+            .map(|module| escape(&module_name(module)))
+            .unwrap_or_default(),
+        nav,
+        body
+    )
+}
+
+/// The root module, then its imports
+fn modules(program: &Program) -> impl Iterator<Item = &Module> {
+    program.modules.iter().rev()
+}
+
+/// The root module is named after its file
+fn module_name(module: &Module) -> String {
+    if module.name.is_empty() {
+        module
+            .path
+            .file_stem()
+            .map(|stem| stem.to_string_lossy().into_owned())
+            .unwrap_or_default()
+    } else {
+        module.name.clone()
+    }
+}
+
+fn anchor(module: &str, definition: &Definition) -> String {
+    format!("{}.{}", module, definition.name)
+}
+
+/// `name ( a b -- c )`, or the name alone without a declared stack effect
+fn signature(definition: &Definition) -> String {
+    match &definition.effect {
+        Some(effect) => {
+            let mut parts = vec![definition.name.as_str(), "("];
+            parts.extend(effect.inputs.iter().map(String::as_str));
+            parts.push("--");
+            parts.extend(effect.outputs.iter().map(String::as_str));
+            parts.push(")");
+            parts.join(" ")
+        }
+        None => definition.name.clone(),
+    }
+}
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
@@ -0,0 +1,216 @@
+use crate::lang::{
+    parser::{ParseError, Parser},
+    token::TokenKind,
+    trivia::{tokenize_lossless, LosslessToken, TriviaKind},
+};
+
+const INDENT: usize = 4;
+const MAX_WIDTH: usize = 80;
+
+/// A line of output, blocks are indented by `depth` levels
+#[derive(Debug)]
+struct Line {
+    depth: usize,
+    parts: Vec<String>,
+    comment: Option<String>,
+}
+
+impl Line {
+    fn new(depth: usize) -> Self {
+        Self {
+            depth,
+            parts: vec![],
+            comment: None,
+        }
+    }
+
+    /// A `def name = body` written on a single line
+    fn is_inline_definition(&self) -> bool {
+        self.depth == 0 && self.parts.len() > 3 && self.parts[0] == "def" && self.parts[2] == "="
+    }
+}
+
+#[derive(Debug)]
+enum Row {
+    Blank,
+    Line(Line),
+}
+
+/// Formats a source file into the canonical layout:
+///
+/// - blocks are indented by 4 spaces per level
+/// - tokens on a line are separated by a single space, but none inside
+///   list brackets or before commas
+/// - comments are kept, blank lines are collapsed
+/// - consecutive single line definitions have their bodies aligned
+/// - lines longer than 80 columns have their outermost closure wrapped
+///
+/// Sources with syntax errors are not formatted
+pub fn format(source: &str) -> Result<String, ParseError> {
+    Parser::new(source).parse()?;
+    let tokens = tokenize_lossless(source)?;
+
+    let mut rows = rows(&tokens);
+    align_definitions(&mut rows);
+
+    let mut out = String::new();
+    let mut blank = false;
+    for row in &rows {
+        match row {
+            Row::Blank => blank = true,
+            Row::Line(line) => {
+                if blank && !out.is_empty() {
+                    out.push('\n');
+                }
+                blank = false;
+                render_line(&mut out, line.depth, &line.parts, line.comment.as_deref());
+            }
+        }
+    }
+    Ok(out)
+}
+
+fn rows(tokens: &[LosslessToken]) -> Vec<Row> {
+    let mut rows = vec![];
+    let mut depth: usize = 0;
+    let mut current: Option<Line> = None;
+
+    for token in tokens {
+        match token.kind() {
+            TokenKind::Indent => depth += 1,
+            TokenKind::Dedent => depth = depth.saturating_sub(1),
+            kind => {
+                let mut after_comment = false;
+                for trivia in &token.leading {
+                    match trivia.kind {
+                        TriviaKind::Comment => {
+                            let mut line = Line::new(depth);
+                            line.comment = Some(trivia.text.trim_end().to_string());
+                            rows.push(Row::Line(line));
+                            after_comment = true;
+                        }
+                        TriviaKind::Newline if !after_comment => rows.push(Row::Blank),
+                        TriviaKind::Newline => after_comment = false,
+                        TriviaKind::Whitespace => {}
+                    }
+                }
+                if kind == TokenKind::Eof {
+                    break;
+                }
+
+                current
+                    .get_or_insert_with(|| Line::new(depth))
+                    .parts
+                    .push(token.text.to_string());
+                for trivia in &token.trailing {
+                    match trivia.kind {
+                        TriviaKind::Comment => {
+                            if let Some(line) = current.as_mut() {
+                                line.comment = Some(trivia.text.trim_end().to_string());
+                            }
+                        }
+                        TriviaKind::Newline => rows.extend(current.take().map(Row::Line)),
+                        TriviaKind::Whitespace => {}
+                    }
+                }
+            }
+        }
+    }
+    rows.extend(current.take().map(Row::Line));
+    rows
+}
+
+/// Pads the names of consecutive single line definitions to the same width
+fn align_definitions(rows: &mut [Row]) {
+    let mut start = 0;
+    while start < rows.len() {
+        let mut end = start;
+        while end < rows.len() && is_aligned_definition(rows, end) {
+            end += 1;
+        }
+
+        if end - start > 1 {
+            let width = rows[start..end]
+                .iter()
+                .filter_map(|row| match row {
+                    Row::Line(line) => Some(line.parts[1].chars().count()),
+                    Row::Blank => None,
+                })
+                .max()
+                .unwrap_or_default();
+            for row in &mut rows[start..end] {
+                if let Row::Line(line) = row {
+                    let padding = width - line.parts[1].chars().count();
+                    line.parts[1].push_str(&" ".repeat(padding));
+                }
+            }
+        }
+        start = end.max(start + 1);
+    }
+}
+
+/// Single line definitions not continued on the following lines
+fn is_aligned_definition(rows: &[Row], idx: usize) -> bool {
+    let continued = matches!(rows.get(idx + 1), Some(Row::Line(next)) if next.depth > 0);
+    matches!(&rows[idx], Row::Line(line) if line.is_inline_definition()) && !continued
+}
+
+fn render_line(out: &mut String, depth: usize, parts: &[String], comment: Option<&str>) {
+    let mut text = " ".repeat(depth * INDENT);
+    for (idx, part) in parts.iter().enumerate() {
+        if idx > 0 && is_spaced(&parts[idx - 1], part) {
+            text.push(' ');
+        }
+        text.push_str(part);
+    }
+    if let Some(comment) = comment {
+        if !parts.is_empty() {
+            text.push(' ');
+        }
+        text.push_str(comment);
+    }
+
+    let width = text.split('\n').next().unwrap_or_default().chars().count();
+    if width > MAX_WIDTH {
+        if let Some((open, close)) = outermost_closure(parts) {
+            render_line(out, depth, &parts[..=open], None);
+            render_line(out, depth + 1, &parts[open + 1..close], None);
+            render_line(out, depth, &parts[close..], comment);
+            return;
+        }
+    }
+
+    out.push_str(text.trim_end_matches(' '));
+    out.push('\n');
+}
+
+/// Tokens are separated by a space, except after a quote and inside the
+/// brackets of a list, and commas stick to the entry they end
+fn is_spaced(previous: &str, part: &str) -> bool {
+    !matches!(previous, "\\" | "[") && !matches!(part, "]" | ",")
+}
+
+/// Position of the first non empty closure opened and closed within the parts
+fn outermost_closure(parts: &[String]) -> Option<(usize, usize)> {
+    let mut open = None;
+    let mut depth = 0;
+    for (idx, part) in parts.iter().enumerate() {
+        match part.as_str() {
+            "{" => {
+                if depth == 0 {
+                    open = Some(idx);
+                }
+                depth += 1;
+            }
+            "}" if depth > 0 => {
+                depth -= 1;
+                match open {
+                    Some(open) if depth == 0 && idx > open + 1 => return Some((open, idx)),
+                    _ => {}
+                }
+            }
+            _ => {}
+        }
+    }
+    None
+}
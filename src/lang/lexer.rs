@@ -1,27 +1,95 @@
-use crate::lang::{
-    parser::{ParseError, ParseErrorKind},
-    span::Span,
-    token::{Token, TokenKind},
+use crate::{
+    lang::{
+        parser::{ParseError, ParseErrorKind},
+        source::FileId,
+        span::Span,
+        token::{StringPart, Token, TokenKind, TokenValue},
+        trivia::{Trivia, TriviaKind},
+    },
+    trace::{Event, NullTracer, StdoutTracer, Tracer},
 };
-use std::{collections::VecDeque, str::FromStr};
-use tap::TapOptional;
+use std::{borrow::Cow, collections::VecDeque, str::FromStr};
 
-pub struct Lexer {
-    source: Vec<char>,
+/// Splits a source into tokens, spans are byte offsets into the source.
+/// Iterating yields a final `Eof` token once the source is consumed
+pub struct Lexer<'a> {
+    file: FileId,
+    source: &'a str,
     start_pos: usize,
     current_pos: usize,
-    queue: VecDeque<Token>,
+    queue: VecDeque<Token<'a>>,
     indents: Vec<usize>,
+    // Whitespace and comments skipped so far, only kept by lossless lexers
+    lossless: bool,
+    trivia: Vec<Trivia<'a>>,
+    tracer: Box<dyn Tracer + 'a>,
+    done: bool,
 }
 
-impl Lexer {
-    pub fn new(source: &str) -> Self {
+impl<'a> Lexer<'a> {
+    pub fn new(source: &'a str) -> Self {
         Self {
-            source: source.chars().collect(),
+            file: FileId::default(),
+            source,
             start_pos: 0,
             current_pos: 0,
             queue: VecDeque::new(),
             indents: vec![0],
+            lossless: false,
+            trivia: vec![],
+            tracer: Box::new(NullTracer),
+            done: false,
+        }
+    }
+
+    /// A lexer that keeps the whitespace and comments it skips, see
+    /// [`Lexer::take_trivia`]
+    pub fn lossless(source: &'a str) -> Self {
+        Self {
+            lossless: true,
+            ..Self::new(source)
+        }
+    }
+
+    /// Spans of the tokens point into the given file
+    pub fn with_file(self, file: FileId) -> Self {
+        Self { file, ..self }
+    }
+
+    pub fn file(&self) -> FileId {
+        self.file
+    }
+
+    pub fn source(&self) -> &'a str {
+        self.source
+    }
+
+    /// Prints the produced tokens to stdout, see [`Lexer::set_tracer`]
+    pub fn tracing(&mut self, tracing: bool) {
+        if tracing {
+            self.set_tracer(StdoutTracer);
+        } else {
+            self.set_tracer(NullTracer);
+        }
+    }
+
+    pub fn set_tracer(&mut self, tracer: impl Tracer + 'a) {
+        self.tracer = Box::new(tracer);
+    }
+
+    /// Returns the trivia skipped since the last call, in source order
+    pub fn take_trivia(&mut self) -> Vec<Trivia<'a>> {
+        std::mem::take(&mut self.trivia)
+    }
+
+    /// Records the text from `start` to the current position as trivia
+    fn trivia(&mut self, kind: TriviaKind, start: usize) {
+        if self.lossless && self.current_pos > start {
+            self.trivia.push(Trivia {
+                kind,
+                span: Span::in_file(self.file, start, self.current_pos - start),
+                text: &self.source[start..self.current_pos],
+            });
         }
     }
 
@@ -29,6 +97,14 @@ impl Lexer {
         ParseError::new(self.span().into(), error)
     }
 
+    /// An error spanning from `start` to the current position
+    fn error_from(&self, start: usize, error: ParseErrorKind) -> ParseError {
+        ParseError::new(
+            Some(Span::in_file(self.file, start, self.current_pos - start)),
+            error,
+        )
+    }
+
     fn indent_level(&self) -> usize {
         self.indents.last().copied().unwrap_or(0)
     }
@@ -51,81 +127,48 @@ impl Lexer {
     }
 
     fn span(&self) -> Span {
-        Span::new(self.start_pos, self.current_pos - self.start_pos)
+        Span::in_file(self.file, self.start_pos, self.current_pos - self.start_pos)
     }
 
-    fn token(&mut self, mut token: Token) {
+    fn token(&mut self, mut token: Token<'a>) {
         token.span = self.span();
-        println!("{:?}", token);
+        self.tracer.trace(&Event::Token(&token));
         self.queue.push_back(token);
         self.start_pos = self.current_pos;
     }
 
+    fn rest(&self) -> &'a str {
+        self.source.get(self.current_pos..).unwrap_or_default()
+    }
+
     fn current(&self) -> Option<char> {
-        self.source.get(self.current_pos).copied()
+        self.rest().chars().next()
     }
 
     fn peek(&self) -> Option<char> {
-        self.source.get(self.current_pos + 1).copied()
+        self.rest().chars().nth(1)
     }
 
     fn advance(&mut self) {
-        self.current_pos += 1
+        self.current_pos += self.current().map_or(0, char::len_utf8)
     }
 
     fn is_line_start(&self) -> bool {
-        if self.current_pos > 1 {
-            self.source
-                .get(self.current_pos - 1)
-                .map_or(false, |c| *c == '\n')
-        } else {
-            true
-        }
+        self.current_pos == 0 || self.source[..self.current_pos].ends_with('\n')
     }
 
-    fn trace(&self) {
-        //return;
-        let src: String = self
-            .source
-            .iter()
-            .map(|c| match *c {
-                '\n' => '↵',
-                c => c,
-            })
-            .collect();
-
-        print!("[");
-        for level in &self.indents {
-            print!("{},", level);
-        }
-        println!("]");
-        println!("{}", src);
-        println!("{}↑", " ".repeat(self.current_pos));
-    }
-
-    pub fn next(&mut self) -> Result<Token, ParseError> {
-        if let Some(token) = self.queue.pop_front() {
-            return Ok(token);
-        }
-
-        self.read_token()?;
-
-        Ok(self
-            .queue
-            .pop_front()
-            .unwrap_or(Token::new(self.span(), TokenKind::Eof)))
+    /// The token ending the source
+    pub fn eof(&self) -> Token<'a> {
+        Token::new(
+            Span::in_file(self.file, self.source.len(), 0),
+            TokenKind::Eof,
+        )
     }
 
     fn read_token(&mut self) -> Result<(), ParseError> {
-        self.trace();
-
         if self.is_line_start() {
-            self.read_indent()?;
-            if let Some(' ') = self.current() {
-                return self.read_token();
-            } else {
-                self.trace();
-            }
+            // Blank and comment-only lines don't affect indentation
+            while self.read_indent()? {}
         }
 
         if let Some(c) = self.current() {
@@ -133,12 +176,18 @@ impl Lexer {
                 '0'..='9' => {
                     self.read_number()?;
                 }
+                '-' if self.peek().is_some_and(|c| c.is_ascii_digit()) => {
+                    self.read_number()?;
+                }
                 '\'' => {
                     self.read_char()?;
                 }
                 '"' => {
                     self.read_string()?;
                 }
+                'r' if self.peek() == Some('"') => {
+                    self.read_string()?;
+                }
                 '{' => {
                     self.advance();
                     self.token(Token::of(TokenKind::LBrace));
@@ -147,6 +196,26 @@ impl Lexer {
                     self.advance();
                     self.token(Token::of(TokenKind::RBrace));
                 }
+                '(' => {
+                    self.advance();
+                    self.token(Token::of(TokenKind::LParen));
+                }
+                '[' => {
+                    self.advance();
+                    self.token(Token::of(TokenKind::LBracket));
+                }
+                ']' => {
+                    self.advance();
+                    self.token(Token::of(TokenKind::RBracket));
+                }
+                ',' => {
+                    self.advance();
+                    self.token(Token::of(TokenKind::Comma));
+                }
+                ')' => {
+                    self.advance();
+                    self.token(Token::of(TokenKind::RParen));
+                }
                 '\\' => {
                     self.advance();
                     self.token(Token::of(TokenKind::Backslash));
@@ -197,15 +266,26 @@ impl Lexer {
         }
     }
 
+    fn try_read_str(&mut self, s: &str) -> bool {
+        let start = self.current_pos;
+        for c in s.chars() {
+            if !self.try_read_exact(c) {
+                self.current_pos = start;
+                return false;
+            }
+        }
+        true
+    }
+
     fn read_exact(&mut self, c: char) -> Result<(), ParseError> {
         self.try_read_exact(c)
             .then_some(())
             .ok_or_else(|| self.error(ParseErrorKind::UnexpectedChar(c)))
     }
 
-    fn try_read_digit(&mut self) -> Option<char> {
+    fn try_read_digit(&mut self, radix: u32) -> Option<char> {
         match self.current() {
-            Some(c @ '0'..='9') => {
+            Some(c) if c.is_digit(radix) => {
                 self.advance();
                 Some(c)
             }
@@ -213,92 +293,343 @@ impl Lexer {
         }
     }
 
-    fn read_digit(&mut self) -> Result<char, ParseError> {
-        self.try_read_digit()
+    fn read_digit(&mut self, radix: u32) -> Result<char, ParseError> {
+        self.try_read_digit(radix)
             .ok_or_else(|| self.error(ParseErrorKind::ExpectedDigit))
     }
 
-    fn read_integer(&mut self) -> Result<String, ParseError> {
+    /// Reads digits in the given radix, separated by optional underscores
+    fn read_integer(&mut self, radix: u32) -> Result<String, ParseError> {
         let mut num = String::new();
-        num.push(self.read_digit()?);
-        while let Some(c) = self.try_read_digit() {
-            num.push(c);
+        num.push(self.read_digit(radix)?);
+        loop {
+            if let Some(c) = self.try_read_digit(radix) {
+                num.push(c);
+            } else if self.try_read_exact('_') {
+                num.push(self.read_digit(radix)?);
+            } else {
+                break;
+            }
         }
         Ok(num)
     }
 
+    fn literal(&self) -> String {
+        self.source[self.start_pos..self.current_pos].to_string()
+    }
+
+    /// Numbers are decimal ints and floats with an optional exponent,
+    /// or ints prefixed by `0x`, `0o` or `0b`.
+    /// A `-` directly followed by a digit is part of the literal
     fn read_number(&mut self) -> Result<(), ParseError> {
-        let num = self.read_integer()?;
+        let sign = if self.try_read_exact('-') { "-" } else { "" };
+
+        let radix = match (self.current(), self.peek()) {
+            (Some('0'), Some('x')) => 16,
+            (Some('0'), Some('o')) => 8,
+            (Some('0'), Some('b')) => 2,
+            _ => 10,
+        };
+        if radix != 10 {
+            self.advance();
+            self.advance();
+            let num = format!("{}{}", sign, self.read_integer(radix)?);
+            // Digits of a larger radix or letters can't follow the literal
+            if self.current().is_some_and(is_term) {
+                while self.current().is_some_and(is_term) {
+                    self.advance();
+                }
+                return Err(self.error(ParseErrorKind::ParseInt(self.literal())));
+            }
+            let value = i64::from_str_radix(&num, radix)
+                .map_err(|_| self.error(ParseErrorKind::ParseInt(self.literal())))?;
+            self.token(Token::with_int(TokenKind::Int, value));
+            return Ok(());
+        }
+
+        let mut num = format!("{}{}", sign, self.read_integer(10)?);
+        let mut float = false;
 
         if self.try_read_exact('.') {
-            let fract = self.read_integer()?;
-            let num = format!("{}.{}", num, fract);
-            self.token(Token::with_float(
-                TokenKind::Float,
-                f64::from_str(&num).map_err(|_| self.error(ParseErrorKind::ParseFloat(num)))?,
-            ));
-            Ok(())
+            num = format!("{}.{}", num, self.read_integer(10)?);
+            float = true;
+        }
+        if self.try_read_exact('e') || self.try_read_exact('E') {
+            let exp_sign = if self.try_read_exact('-') {
+                "-"
+            } else {
+                self.try_read_exact('+');
+                ""
+            };
+            num = format!("{}e{}{}", num, exp_sign, self.read_integer(10)?);
+            float = true;
+        }
+
+        if float {
+            let value = f64::from_str(&num)
+                .ok()
+                .filter(|v| v.is_finite())
+                .ok_or_else(|| self.error(ParseErrorKind::ParseFloat(self.literal())))?;
+            self.token(Token::with_float(TokenKind::Float, value));
         } else {
-            self.token(Token::with_int(
-                TokenKind::Int,
-                i64::from_str(&num).map_err(|_| self.error(ParseErrorKind::ParseInt(num)))?,
-            ));
-            Ok(())
+            let value = i64::from_str(&num)
+                .map_err(|_| self.error(ParseErrorKind::ParseInt(self.literal())))?;
+            self.token(Token::with_int(TokenKind::Int, value));
         }
+        Ok(())
     }
 
     fn read_char(&mut self) -> Result<(), ParseError> {
         self.read_exact('\'')?;
-        let c = self.read()?;
+        let c = match self.current() {
+            Some('\\') => self.read_escape()?,
+            _ => self.read()?,
+        };
         self.read_exact('\'')?;
         self.token(Token::with_char(TokenKind::Char, c));
         Ok(())
     }
 
+    /// Reads `"..."`, `"""..."""` or their raw `r` prefixed forms.
+    /// Non raw strings support escapes and `{...}` interpolation
     fn read_string(&mut self) -> Result<(), ParseError> {
-        let mut s = String::new();
-
+        let raw = self.try_read_exact('r');
         self.read_exact('"')?;
-        loop {
-            let c = self.read()?;
-            if c == '"' {
-                break;
-            } else {
-                s.push(c);
+
+        let parts = if self.try_read_str("\"\"") {
+            self.read_multiline_string(raw)?
+        } else {
+            self.read_string_parts(raw, None)?
+        };
+
+        match parts.as_slice() {
+            [] => self.token(Token::with_string(TokenKind::String, "")),
+            [StringPart::Literal(s)] => {
+                self.token(Token::with_string(TokenKind::String, s.clone()))
             }
+            _ => self.token(Token::with(
+                TokenKind::InterpolatedString,
+                TokenValue::Parts(parts),
+            )),
         }
 
-        self.token(Token::with_string(TokenKind::String, s));
-
         Ok(())
     }
 
-    fn read_term(&mut self) -> Result<(), ParseError> {
-        let mut id = String::new();
+    /// The indentation of the closing `"""` is removed from every line, and
+    /// the line breaks after the opening and before the closing quotes are
+    /// dropped
+    fn read_multiline_string(&mut self, raw: bool) -> Result<Vec<StringPart<'a>>, ParseError> {
+        let indent = self.closing_indent(raw);
+        if self.try_read_exact('\n') {
+            self.skip_indent(indent);
+        }
+        self.read_string_parts(raw, Some(indent))
+    }
+
+    /// Indentation of the line of the closing `"""`, if nothing else precedes them
+    fn closing_indent(&self, raw: bool) -> usize {
+        let bytes = self.source.as_bytes();
+        let mut pos = self.current_pos;
+        while pos < bytes.len() && !bytes[pos..].starts_with(b"\"\"\"") {
+            pos += if !raw && bytes[pos] == b'\\' { 2 } else { 1 };
+        }
+
+        let pos = pos.min(bytes.len());
+        let line_start = bytes[..pos]
+            .iter()
+            .rposition(|b| *b == b'\n')
+            .map_or(0, |idx| idx + 1);
+        let spaces = &bytes[line_start..pos];
+        if line_start > self.current_pos && spaces.iter().all(|b| *b == b' ') {
+            spaces.len()
+        } else {
+            0
+        }
+    }
+
+    fn skip_indent(&mut self, indent: usize) {
+        for _ in 0..indent {
+            if !self.try_read_exact(' ') {
+                break;
+            }
+        }
+    }
+
+    /// Reads until the closing quotes, `indent` is set for multiline strings
+    fn read_string_parts(
+        &mut self,
+        raw: bool,
+        indent: Option<usize>,
+    ) -> Result<Vec<StringPart<'a>>, ParseError> {
+        let mut parts = vec![];
+        let mut literal = String::new();
+        let mut literal_start = self.current_pos;
+
+        let end = loop {
+            let end = self.current_pos;
+            match (self.current(), indent) {
+                (Some('"'), None) => {
+                    self.advance();
+                    break end;
+                }
+                (Some('"'), Some(_)) if self.try_read_str("\"\"\"") => break end,
+                (Some('\n'), Some(indent)) => {
+                    self.advance();
+                    // The line break before the closing quotes isn't part of the string
+                    let start = self.current_pos;
+                    self.skip_indent(indent);
+                    while self.try_read_exact(' ') {}
+                    if self.try_read_str("\"\"\"") {
+                        break end;
+                    }
+                    self.current_pos = start;
+                    self.skip_indent(indent);
+                    literal.push('\n');
+                }
+                (Some('\\'), _) if !raw => literal.push(self.read_escape()?),
+                (Some('{'), _) if !raw => {
+                    if !literal.is_empty() {
+                        let literal = std::mem::take(&mut literal);
+                        parts.push(self.literal_part(literal_start, end, literal));
+                    }
+                    parts.push(self.read_interpolation()?);
+                    literal_start = self.current_pos;
+                }
+                _ => literal.push(self.read()?),
+            }
+        };
 
-        while let Some(c) = self.try_read_fn(is_term) {
-            id.push(c);
+        if !literal.is_empty() {
+            parts.push(self.literal_part(literal_start, end, literal));
         }
+        Ok(parts)
+    }
+
+    /// Borrows the source of a literal when it was written without escapes
+    fn literal_part(&self, start: usize, end: usize, literal: String) -> StringPart<'a> {
+        match self.source.get(start..end) {
+            Some(text) if text == literal => StringPart::Literal(Cow::Borrowed(text)),
+            _ => StringPart::Literal(Cow::Owned(literal)),
+        }
+    }
+
+    /// Reads the source of a `{...}` interpolation, up to the matching brace
+    fn read_interpolation(&mut self) -> Result<StringPart<'a>, ParseError> {
+        let start = self.current_pos;
+        self.read_exact('{')?;
+
+        let offset = self.current_pos;
+        let mut depth = 0;
+        let end = loop {
+            match self.current() {
+                None => {
+                    return Err(self.error_from(start, ParseErrorKind::UnterminatedInterpolation))
+                }
+                Some('}') if depth == 0 => {
+                    let end = self.current_pos;
+                    self.advance();
+                    break end;
+                }
+                Some(c) => {
+                    match c {
+                        '{' => depth += 1,
+                        '}' => depth -= 1,
+                        _ => {}
+                    }
+                    self.advance();
+                }
+            }
+        };
+
+        Ok(StringPart::Code {
+            source: &self.source[offset..end],
+            offset,
+        })
+    }
+
+    fn read_escape(&mut self) -> Result<char, ParseError> {
+        let start = self.current_pos;
+        self.read_exact('\\')?;
+
+        let c = match self.read()? {
+            'n' => '\n',
+            't' => '\t',
+            '0' => '\0',
+            c @ ('\\' | '"' | '\'' | '{' | '}') => c,
+            'u' => {
+                let mut hex = String::new();
+                let braced = self.try_read_exact('{');
+                while let Some(c) = self.try_read_fn(|c| c.is_ascii_alphanumeric()) {
+                    hex.push(c);
+                }
+                let closed = self.try_read_exact('}');
+
+                let c = (braced && closed && (1..=6).contains(&hex.len()))
+                    .then(|| u32::from_str_radix(&hex, 16).ok())
+                    .flatten()
+                    .and_then(char::from_u32);
+                match c {
+                    Some(c) => c,
+                    None => return Err(self.invalid_escape(start)),
+                }
+            }
+            _ => return Err(self.invalid_escape(start)),
+        };
+
+        Ok(c)
+    }
+
+    fn invalid_escape(&self, start: usize) -> ParseError {
+        let escape = self.source[start..self.current_pos].to_string();
+        self.error_from(start, ParseErrorKind::InvalidEscape(escape))
+    }
+
+    fn read_term(&mut self) -> Result<(), ParseError> {
+        let start = self.current_pos;
+        while self.try_read_fn(is_term).is_some() {}
+        let id = &self.source[start..self.current_pos];
 
-        match id.as_str() {
+        match id {
             "def" => self.token(Token::of(TokenKind::Def)),
+            "import" => self.token(Token::of(TokenKind::Import)),
             "=" => self.token(Token::of(TokenKind::Eq)),
-            _ => self.token(Token::with_string(TokenKind::Term, id)),
+            "->" => self.token(Token::of(TokenKind::Arrow)),
+            _ => match id.strip_suffix(':') {
+                Some(key) if !key.is_empty() => self.token(Token::with_string(TokenKind::Key, key)),
+                _ => self.token(Token::with_string(TokenKind::Term, id)),
+            },
         }
 
         Ok(())
     }
 
-    fn read_indent(&mut self) -> Result<(), ParseError> {
+    /// Reads the indentation of a line, returns true if the line was skipped
+    fn read_indent(&mut self) -> Result<bool, ParseError> {
+        let start = self.current_pos;
         let mut count = 0;
 
         while self.try_read_exact(' ') {
             count += 1;
         }
+        self.trivia(TriviaKind::Whitespace, start);
+        self.start_pos = self.current_pos;
 
         match self.current() {
             Some('\r') | Some('\n') => {
                 self.skip_whitespace();
+                return Ok(true);
+            }
+            // Only comments at the indentation of definitions document them
+            Some('#') if self.peek() == Some('#') && count == 0 && !self.lossless => {
+                self.read_doc_comment()?;
+                self.skip_whitespace();
+                return Ok(true);
+            }
+            Some('#') => {
+                self.skip_comment()?;
+                self.skip_whitespace();
+                return Ok(true);
             }
             _ => {
                 if count > self.indent_level() {
@@ -316,10 +647,11 @@ impl Lexer {
                 }
             }
         }
-        Ok(())
+        Ok(false)
     }
 
     fn skip_comment(&mut self) -> Result<(), ParseError> {
+        let start = self.current_pos;
         self.read_exact('#')?;
         while let Some(c) = self.current() {
             if c == '\n' {
@@ -327,24 +659,82 @@ impl Lexer {
             }
             self.advance();
         }
+        self.trivia(TriviaKind::Comment, start);
         Ok(())
     }
 
-    fn skip_whitespace(&mut self) {
-        loop {
-            match self.current() {
-                Some(' ') | Some('\r') => {
-                    self.advance();
-                }
-                Some('\n') => {
-                    self.advance();
-                    break;
-                }
-                _ => break,
+    /// Doc comments are kept as tokens, without their `##` and the space
+    /// following it
+    fn read_doc_comment(&mut self) -> Result<(), ParseError> {
+        self.read_exact('#')?;
+        self.read_exact('#')?;
+        self.try_read_exact(' ');
+        let start = self.current_pos;
+        while self.current().is_some_and(|c| c != '\n') {
+            self.advance();
+        }
+        let text = self.source[start..self.current_pos].trim_end();
+        self.token(Token::with_string(TokenKind::DocComment, text));
+        Ok(())
+    }
+
+    fn skip_line(&mut self) {
+        while let Some(c) = self.current() {
+            self.advance();
+            if c == '\n' {
+                break;
             }
         }
         self.start_pos = self.current_pos;
     }
+
+    fn skip_whitespace(&mut self) {
+        let start = self.current_pos;
+        while let Some(' ') | Some('\r') = self.current() {
+            self.advance();
+        }
+        self.trivia(TriviaKind::Whitespace, start);
+
+        let start = self.current_pos;
+        if self.try_read_exact('\n') {
+            self.trivia(TriviaKind::Newline, start);
+        }
+        self.start_pos = self.current_pos;
+    }
+}
+
+impl<'a> Iterator for Lexer<'a> {
+    type Item = Result<Token<'a>, ParseError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        while self.queue.is_empty() && self.current().is_some() {
+            if let Err(error) = self.read_token() {
+                self.tracer.trace(&Event::Error(&error));
+                // Resume lexing from the next line
+                self.skip_line();
+                return Some(Err(error));
+            }
+        }
+
+        if self.queue.is_empty() {
+            // Close any block still open at the end of the source
+            let count = self.pop_indent_level(0);
+            for _ in 0..count {
+                self.token(Token::of(TokenKind::Dedent));
+            }
+        }
+
+        match self.queue.pop_front() {
+            Some(token) => Some(Ok(token)),
+            None if self.done => None,
+            None => {
+                self.done = true;
+                let eof = self.eof();
+                self.tracer.trace(&Event::Token(&eof));
+                Some(Ok(eof))
+            }
+        }
+    }
 }
 
 fn is_term_lead(c: char) -> bool {
@@ -373,6 +763,7 @@ fn is_term(c: char) -> bool {
         'a'..='z'
         | 'A'..='Z'
         | '0'..='9'
+        | '.'
         | '='
         | '+'
         | '-'
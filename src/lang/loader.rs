@@ -0,0 +1,189 @@
+use crate::lang::{
+    ast::Ast,
+    diagnostic::Diagnostic,
+    parser::{ParseError, Parser},
+    source::{FileId, SourceMap},
+};
+use std::{
+    fs, io,
+    path::{Path, PathBuf},
+};
+use thiserror::Error;
+
+pub const EXTENSION: &str = "mana";
+
+#[derive(Debug, Error)]
+pub enum LoadError {
+    #[error("Could not read '{}': {source}", .path.display())]
+    Io { path: PathBuf, source: io::Error },
+    #[error(transparent)]
+    Parse(#[from] ParseError),
+    #[error("Module '{module}' not found, imported from '{}'", .importer.display())]
+    ModuleNotFound { module: String, importer: PathBuf },
+    #[error("Import cycle: {}", .0.join(" -> "))]
+    ImportCycle(Vec<String>),
+    #[error(
+        "Module '{module}' names both '{}' and '{}'",
+        .first.display(),
+        .second.display()
+    )]
+    ModuleConflict {
+        module: String,
+        first: PathBuf,
+        second: PathBuf,
+    },
+}
+
+impl LoadError {
+    /// Renders the error, with a source snippet when it points into a file
+    pub fn render(&self, sources: &SourceMap) -> String {
+        match self {
+            LoadError::Parse(error) => Diagnostic::from(error).render(sources),
+            error => format!("error: {}\n", error),
+        }
+    }
+}
+
+/// A parsed source file, its definitions are namespaced by its name.
+/// The root module has an empty name, its definitions are not namespaced
+#[derive(Debug)]
+pub struct Module {
+    pub name: String,
+    pub path: PathBuf,
+    pub file: FileId,
+    pub ast: Ast,
+}
+
+/// A root module and everything it imports, dependencies come first
+#[derive(Debug)]
+pub struct Program {
+    pub modules: Vec<Module>,
+}
+
+/// Resolves imports to files, `import Data.List` is looked up as
+/// `Data/List.mana` next to the importing file, then next to the root
+/// module, then in each search path.
+/// Loaded sources are added to a [`SourceMap`]
+#[derive(Debug, Default)]
+pub struct Loader {
+    search_paths: Vec<PathBuf>,
+    // Directory of the root module
+    root: PathBuf,
+    modules: Vec<Module>,
+    // Names and paths of the modules whose imports are being loaded
+    loading: Vec<(String, PathBuf)>,
+}
+
+impl Loader {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_search_path(mut self, path: impl Into<PathBuf>) -> Self {
+        self.search_paths.push(path.into());
+        self
+    }
+
+    pub fn load(
+        mut self,
+        sources: &mut SourceMap,
+        path: impl AsRef<Path>,
+    ) -> Result<Program, LoadError> {
+        self.root = path
+            .as_ref()
+            .parent()
+            .unwrap_or(Path::new(""))
+            .to_path_buf();
+        self.load_module(sources, String::new(), path.as_ref().to_path_buf())?;
+        Ok(Program {
+            modules: self.modules,
+        })
+    }
+
+    fn load_module(
+        &mut self,
+        sources: &mut SourceMap,
+        name: String,
+        path: PathBuf,
+    ) -> Result<(), LoadError> {
+        let source = fs::read_to_string(&path).map_err(|source| LoadError::Io {
+            path: path.clone(),
+            source,
+        })?;
+        let file = sources.add(path.display().to_string(), source.clone());
+        let ast = Parser::new(&source).with_file(file).parse()?;
+
+        self.loading.push((name.clone(), path.clone()));
+        for import in &ast.imports {
+            let import_path = self.resolve(import, &path)?;
+            if let Some(idx) = self
+                .loading
+                .iter()
+                .position(|(_, loading)| same_file(loading, &import_path))
+            {
+                let mut cycle: Vec<String> = self.loading[idx..]
+                    .iter()
+                    .map(|(name, _)| name.clone())
+                    .collect();
+                cycle.push(import.clone());
+                return Err(LoadError::ImportCycle(cycle));
+            }
+
+            // Imports are resolved next to the importing file, so the same
+            // name may point to different files
+            let known = self
+                .modules
+                .iter()
+                .map(|module| (&module.name, &module.path))
+                .chain(self.loading.iter().map(|(name, path)| (name, path)))
+                .find(|(name, _)| *name == import);
+            match known {
+                Some((_, known)) if same_file(known, &import_path) => continue,
+                Some((_, known)) => {
+                    return Err(LoadError::ModuleConflict {
+                        module: import.clone(),
+                        first: known.clone(),
+                        second: import_path,
+                    })
+                }
+                None => self.load_module(sources, import.clone(), import_path)?,
+            }
+        }
+        self.loading.pop();
+
+        self.modules.push(Module {
+            name,
+            path,
+            file,
+            ast,
+        });
+        Ok(())
+    }
+
+    fn resolve(&self, module: &str, importer: &Path) -> Result<PathBuf, LoadError> {
+        let relative: PathBuf = module
+            .split('.')
+            .collect::<PathBuf>()
+            .with_extension(EXTENSION);
+
+        importer
+            .parent()
+            .into_iter()
+            .chain([self.root.as_path()])
+            .chain(self.search_paths.iter().map(PathBuf::as_path))
+            .map(|dir| dir.join(&relative))
+            .find(|candidate| candidate.is_file())
+            .ok_or_else(|| LoadError::ModuleNotFound {
+                module: module.to_string(),
+                importer: importer.to_path_buf(),
+            })
+    }
+}
+
+/// Whether two paths lead to the same file, however they are spelled
+fn same_file(a: &Path, b: &Path) -> bool {
+    match (fs::canonicalize(a), fs::canonicalize(b)) {
+        (Ok(a), Ok(b)) => a == b,
+        _ => a == b,
+    }
+}
@@ -1,5 +1,11 @@
 pub mod ast;
+pub mod diagnostic;
+pub mod doc;
+pub mod formatter;
 pub mod lexer;
+pub mod loader;
 pub mod parser;
+pub mod source;
 pub mod span;
 pub mod token;
+pub mod trivia;
@@ -1,13 +1,15 @@
 use crate::lang::{
-    ast::{Ast, Definition},
+    ast::{Ast, Definition, Entry, Expr, ExprKind, NodeId, Segment, StackEffect},
     lexer::Lexer,
+    source::FileId,
     span::Span,
-    token::{Token, TokenKind},
+    token::{StringPart, Token, TokenKind, TokenValue},
 };
+use std::collections::VecDeque;
 use thiserror::Error;
 
 #[derive(Debug, Error)]
-#[error("{source} at {:?}", .span)]
+#[error("{source}")]
 pub struct ParseError {
     span: Option<Span>,
     source: ParseErrorKind,
@@ -17,6 +19,25 @@ impl ParseError {
     pub fn new(span: Option<Span>, source: ParseErrorKind) -> Self {
         Self { span, source }
     }
+
+    pub fn span(&self) -> Option<Span> {
+        self.span
+    }
+
+    pub fn kind(&self) -> &ParseErrorKind {
+        &self.source
+    }
+
+    /// Moves the error span, for errors in source nested within a file
+    fn offset(self, offset: usize) -> Self {
+        Self {
+            span: self.span.map(|span| Span {
+                start: span.start + offset,
+                ..span
+            }),
+            ..self
+        }
+    }
 }
 
 #[derive(Debug, Error)]
@@ -27,72 +48,540 @@ pub enum ParseErrorKind {
     UnexpectedChar(char),
     #[error("Expected a digit")]
     ExpectedDigit,
-    #[error("Invalid int '{0}'")]
+    #[error("Invalid or out of range int '{0}'")]
     ParseInt(String),
-    #[error("Invalid float '{0}'")]
+    #[error("Invalid or out of range float '{0}'")]
     ParseFloat(String),
+    #[error("Invalid escape sequence '{0}'")]
+    InvalidEscape(String),
+    #[error("Unterminated string interpolation")]
+    UnterminatedInterpolation,
+    #[error("Empty string interpolation")]
+    EmptyInterpolation,
     #[error("Indentation error")]
     IndentationError,
     #[error("Expected token '{expected}', but got '{got}'")]
     ExpectedToken { expected: TokenKind, got: TokenKind },
     #[error("Expected definition but got '{got}'")]
     ExpectedDefinition { got: TokenKind },
+    #[error("Expected expression but got '{got}'")]
+    ExpectedExpression { got: TokenKind },
+    #[error("Expected '--' in stack effect")]
+    ExpectedEffectSeparator,
+}
+
+enum Item {
+    Import(String),
+    Definition(Definition),
 }
 
-pub struct Parser {
-    lexer: Lexer,
+pub struct Parser<'a> {
+    lexer: Lexer<'a>,
+    peeked: Option<Token<'a>>,
+    // Tokens read ahead while looking for the `def` after a doc comment
+    pending: VecDeque<Token<'a>>,
+    depth: usize,
+    next_id: u32,
+    // Where the parsed source starts in its file, for interpolated code
+    offset: usize,
 }
 
-impl Parser {
-    pub fn new(source: &str) -> Self {
+impl<'a> Parser<'a> {
+    pub fn new(source: &'a str) -> Self {
         Self {
             lexer: Lexer::new(source),
+            peeked: None,
+            pending: VecDeque::new(),
+            depth: 0,
+            next_id: 0,
+            offset: 0,
         }
     }
 
-    fn next(&mut self) -> Result<Token, ParseError> {
-        self.lexer.next()
+    /// Spans of the parsed nodes and errors point into the given file
+    pub fn with_file(self, file: FileId) -> Self {
+        Self {
+            lexer: self.lexer.with_file(file),
+            ..self
+        }
     }
 
-    fn expect(&mut self, kind: TokenKind) -> Result<Token, ParseError> {
-        let token = self.next()?;
+    fn node_id(&mut self) -> NodeId {
+        let id = NodeId(self.next_id);
+        self.next_id += 1;
+        id
+    }
+
+    /// Moves a token span to its position in the file
+    fn span(&self, span: Span) -> Span {
+        Span {
+            start: span.start + self.offset,
+            ..span
+        }
+    }
+
+    /// The next token of the lexer, which keeps producing Eof once exhausted.
+    /// Doc comments are folded into the value of the `def` they document,
+    /// those followed by anything else are dropped
+    fn lex(&mut self) -> Result<Token<'a>, ParseError> {
+        if let Some(token) = self.pending.pop_front() {
+            return Ok(token);
+        }
+        let mut doc: Vec<String> = vec![];
+        loop {
+            let mut token = self.lexer.next().unwrap_or_else(|| Ok(self.lexer.eof()))?;
+            match token.kind {
+                TokenKind::DocComment => doc.extend(token.value_string()),
+                // The layout closing the previous definition comes between
+                // a doc comment and its `def`
+                TokenKind::Indent | TokenKind::Dedent if !doc.is_empty() => {
+                    self.pending.push_back(token)
+                }
+                kind => {
+                    if kind == TokenKind::Def && !doc.is_empty() {
+                        token.value = Some(TokenValue::String(doc.join("\n").into()));
+                    }
+                    self.pending.push_back(token);
+                    return Ok(self.pending.pop_front().unwrap_or_else(|| self.lexer.eof()));
+                }
+            }
+        }
+    }
+
+    fn next(&mut self) -> Result<Token<'a>, ParseError> {
+        match self.peeked.take() {
+            Some(token) => Ok(token),
+            None => self.lex(),
+        }
+    }
+
+    fn peek(&mut self) -> Result<&Token<'a>, ParseError> {
+        let token = match self.peeked.take() {
+            Some(token) => token,
+            None => self.lex()?,
+        };
+        Ok(self.peeked.insert(token))
+    }
+
+    /// Consumes the next token if it has the expected kind, a mismatching
+    /// token is left in place so that recovery can resume from it
+    fn expect(&mut self, kind: TokenKind) -> Result<Token<'a>, ParseError> {
+        let token = self.peek()?;
         if token.kind == kind {
-            Ok(token)
+            self.next()
         } else {
-            Err(ParseError::new(
-                Some(token.span),
-                ParseErrorKind::ExpectedToken {
-                    expected: kind,
-                    got: token.kind,
-                },
-            ))
+            Err(expected_token(kind, token))
         }
     }
 
     pub fn parse(&mut self) -> Result<Ast, ParseError> {
-        let token = self.next()?;
+        let (ast, errors) = self.parse_with_recovery();
+        match errors.into_iter().next() {
+            Some(error) => Err(error),
+            None => Ok(ast),
+        }
+    }
 
+    /// Parses the whole source as the body of a definition, such as code
+    /// typed into the repl
+    pub fn parse_code(&mut self, name: impl Into<String>) -> Result<Definition, ParseError> {
+        let id = self.node_id();
+        let body = self.parse_exprs()?;
+        let span = body
+            .first()
+            .zip(body.last())
+            .map_or(Span::in_file(self.lexer.file(), 0, 0), |(first, last)| {
+                first.span.to(last.span)
+            });
+        Ok(Definition {
+            id,
+            span,
+            name: name.into(),
+            doc: None,
+            effect: None,
+            body,
+        })
+    }
+
+    /// Parses the whole source, a syntax error drops the definition it
+    /// appears in and parsing resumes at the next one.
+    /// Returns the definitions that parsed, and every error encountered
+    pub fn parse_with_recovery(&mut self) -> (Ast, Vec<ParseError>) {
+        let mut imports = vec![];
         let mut definitions = vec![];
+        let mut errors = vec![];
+
+        loop {
+            match self.parse_item() {
+                Ok(Some(Item::Import(import))) => imports.push(import),
+                Ok(Some(Item::Definition(definition))) => definitions.push(definition),
+                Ok(None) => break,
+                Err(error) => {
+                    errors.push(error);
+                    self.synchronize(&mut errors);
+                }
+            }
+        }
+
+        (
+            Ast {
+                imports,
+                definitions,
+            },
+            errors,
+        )
+    }
 
+    fn parse_item(&mut self) -> Result<Option<Item>, ParseError> {
+        self.depth = 0;
+        let token = self.next()?;
         match token.kind {
-            TokenKind::Def => definitions.push(self.parse_definition()?),
-            _ => {
-                return Err(ParseError::new(
-                    token.span.into(),
-                    ParseErrorKind::ExpectedDefinition { got: token.kind },
-                ))
+            TokenKind::Import => Ok(Some(Item::Import(self.parse_import()?))),
+            TokenKind::Def => {
+                let doc = token.value_string();
+                Ok(Some(Item::Definition(
+                    self.parse_definition(token.span, doc)?,
+                )))
             }
+            TokenKind::Eof => Ok(None),
+            _ => Err(ParseError::new(
+                token.span.into(),
+                ParseErrorKind::ExpectedDefinition { got: token.kind },
+            )),
         }
+    }
 
-        Ok(Ast {
-            imports: vec![],
-            definitions,
+    /// Skips tokens until the start of the next top level item, that is a
+    /// `def` or `import` outside of any block, or the dedent closing the
+    /// block the error happened in
+    fn synchronize(&mut self, errors: &mut Vec<ParseError>) {
+        loop {
+            let kind = match self.peek() {
+                Ok(token) => token.kind,
+                Err(error) => {
+                    errors.push(error);
+                    continue;
+                }
+            };
+            match kind {
+                TokenKind::Def | TokenKind::Import | TokenKind::Eof if self.depth == 0 => break,
+                TokenKind::Eof => break,
+                TokenKind::Indent => self.depth += 1,
+                TokenKind::Dedent if self.depth <= 1 => {
+                    self.depth = 0;
+                    let _ = self.next();
+                    break;
+                }
+                TokenKind::Dedent => self.depth -= 1,
+                _ => {}
+            }
+            let _ = self.next();
+        }
+    }
+
+    fn parse_import(&mut self) -> Result<String, ParseError> {
+        Ok(self
+            .expect(TokenKind::Term)?
+            .value_string()
+            .unwrap_or_default())
+    }
+
+    fn parse_definition(
+        &mut self,
+        start: Span,
+        doc: Option<String>,
+    ) -> Result<Definition, ParseError> {
+        let id = self.node_id();
+        let name = self
+            .expect(TokenKind::Term)?
+            .value_string()
+            .unwrap_or_default();
+        let effect = match self.peek()?.kind {
+            TokenKind::LParen => Some(self.parse_effect()?),
+            _ => None,
+        };
+        let eq = self.expect(TokenKind::Eq)?;
+        let body = self.parse_body()?;
+
+        let end = body.last().map_or(self.span(eq.span), |expr| expr.span);
+        Ok(Definition {
+            id,
+            span: self.span(start).to(end),
+            name,
+            doc,
+            effect,
+            body,
         })
     }
 
-    fn parse_definition(&mut self) -> Result<Definition, ParseError> {
-        todo!()
+    fn parse_effect(&mut self) -> Result<StackEffect, ParseError> {
+        let start = self.expect(TokenKind::LParen)?.span;
+        let mut inputs = vec![];
+        let mut outputs = None;
+        let end = loop {
+            let token = self.peek()?;
+            match (token.kind, &mut outputs) {
+                (TokenKind::Term, None) if token.value_str() == Some("--") => {
+                    outputs = Some(vec![]);
+                }
+                (TokenKind::Term, None) => inputs.push(token.value_string().unwrap_or_default()),
+                (TokenKind::Term, Some(outputs)) => {
+                    outputs.push(token.value_string().unwrap_or_default())
+                }
+                (TokenKind::RParen, Some(_)) => break self.next()?.span,
+                (TokenKind::RParen, None) => {
+                    return Err(ParseError::new(
+                        Some(token.span),
+                        ParseErrorKind::ExpectedEffectSeparator,
+                    ))
+                }
+                _ => return Err(expected_token(TokenKind::RParen, token)),
+            }
+            self.next()?;
+        };
+
+        Ok(StackEffect {
+            span: self.span(start).to(self.span(end)),
+            inputs,
+            outputs: outputs.unwrap_or_default(),
+        })
     }
 
-    fn parse_expr(&mut self) {}
+    /// A body spans the rest of the line and any block indented below it
+    fn parse_body(&mut self) -> Result<Vec<Expr>, ParseError> {
+        let mut body = vec![];
+        self.depth = 0;
+
+        loop {
+            let kind = self.peek()?.kind;
+            match kind {
+                TokenKind::Def | TokenKind::Import | TokenKind::Eof if self.depth == 0 => break,
+                TokenKind::Indent => {
+                    self.next()?;
+                    self.depth += 1;
+                }
+                TokenKind::Dedent => {
+                    self.next()?;
+                    self.depth = self.depth.saturating_sub(1);
+                    if self.depth == 0 {
+                        break;
+                    }
+                }
+                _ => body.push(self.parse_expr()?),
+            }
+        }
+
+        Ok(body)
+    }
+
+    /// Parses expressions up to one of the closing tokens, which is left in
+    /// place. Groups may span several lines, whatever their indentation
+    fn parse_group(&mut self, closing: &[TokenKind]) -> Result<Vec<Expr>, ParseError> {
+        let mut body = vec![];
+        loop {
+            let kind = self.peek()?.kind;
+            match kind {
+                kind if closing.contains(&kind) => break,
+                TokenKind::Indent => {
+                    self.next()?;
+                    self.depth += 1;
+                }
+                TokenKind::Dedent if self.depth > 0 => {
+                    self.next()?;
+                    self.depth -= 1;
+                }
+                TokenKind::Def | TokenKind::Import | TokenKind::Dedent | TokenKind::Eof => {
+                    return Err(expected_token(closing[0], self.peek()?));
+                }
+                _ => body.push(self.parse_expr()?),
+            }
+        }
+        Ok(body)
+    }
+
+    fn parse_closure(&mut self) -> Result<(Vec<Expr>, Span), ParseError> {
+        let body = self.parse_group(&[TokenKind::RBrace])?;
+        Ok((body, self.next()?.span))
+    }
+
+    fn parse_list(&mut self) -> Result<(Vec<Expr>, Span), ParseError> {
+        let items = self.parse_group(&[TokenKind::RBracket])?;
+        Ok((items, self.next()?.span))
+    }
+
+    /// Entries are separated by commas, a trailing one is allowed
+    fn parse_table(&mut self) -> Result<(Vec<Entry>, Span), ParseError> {
+        let mut entries = vec![];
+        loop {
+            self.skip_layout()?;
+            if self.peek()?.kind == TokenKind::RBrace {
+                break;
+            }
+            let key = self
+                .expect(TokenKind::Key)?
+                .value_string()
+                .unwrap_or_default();
+            let value = self.parse_group(&[TokenKind::Comma, TokenKind::RBrace])?;
+            if value.is_empty() {
+                return Err(ParseError::new(
+                    Some(self.peek()?.span),
+                    ParseErrorKind::ExpectedExpression {
+                        got: self.peek()?.kind,
+                    },
+                ));
+            }
+            entries.push(Entry { key, value });
+            if self.peek()?.kind == TokenKind::Comma {
+                self.next()?;
+            }
+        }
+        Ok((entries, self.next()?.span))
+    }
+
+    /// Skips the indentation changes of a group spanning several lines
+    fn skip_layout(&mut self) -> Result<(), ParseError> {
+        loop {
+            let kind = self.peek()?.kind;
+            match kind {
+                TokenKind::Indent => self.depth += 1,
+                TokenKind::Dedent if self.depth > 0 => self.depth -= 1,
+                _ => return Ok(()),
+            }
+            self.next()?;
+        }
+    }
+
+    /// Interpolated code is parsed on its own, sharing the node ids of the
+    /// enclosing source
+    fn parse_interpolated(&mut self, token: Token<'a>) -> Result<Vec<Segment>, ParseError> {
+        let mut segments = vec![];
+        for part in token.value_parts().unwrap_or_default() {
+            match part {
+                StringPart::Literal(s) => segments.push(Segment::Str(s.into_owned())),
+                StringPart::Code { source, offset } => {
+                    let mut parser = Parser {
+                        next_id: self.next_id,
+                        offset: self.offset + offset,
+                        ..Parser::new(source).with_file(self.lexer.file())
+                    };
+                    let exprs = parser.parse_exprs().map_err(|e| e.offset(offset))?;
+                    if exprs.is_empty() {
+                        // The span covers the braces
+                        let span = Span::in_file(self.lexer.file(), offset - 1, source.len() + 2);
+                        return Err(ParseError::new(
+                            Some(span),
+                            ParseErrorKind::EmptyInterpolation,
+                        ));
+                    }
+                    self.next_id = parser.next_id;
+                    segments.push(Segment::Code(exprs));
+                }
+            }
+        }
+        Ok(segments)
+    }
+
+    /// Parses a plain sequence of expressions, such as interpolated code
+    fn parse_exprs(&mut self) -> Result<Vec<Expr>, ParseError> {
+        let mut exprs = vec![];
+        loop {
+            match self.peek()?.kind {
+                TokenKind::Eof => break,
+                TokenKind::Indent | TokenKind::Dedent => {
+                    self.next()?;
+                }
+                _ => exprs.push(self.parse_expr()?),
+            }
+        }
+        Ok(exprs)
+    }
+
+    /// The names of a binding are the terms following `->` up to the end of
+    /// its line
+    fn parse_binding(&mut self, arrow: Span) -> Result<(Vec<String>, Span), ParseError> {
+        let mut names = vec![];
+        let mut end = arrow;
+        loop {
+            let token = self.peek()?;
+            let (kind, start) = (token.kind, token.span.start);
+            if kind != TokenKind::Term || self.lexer.source()[end.end()..start].contains('\n') {
+                break;
+            }
+            let token = self.next()?;
+            names.push(token.value_string().unwrap_or_default());
+            end = token.span;
+        }
+
+        if names.is_empty() {
+            return Err(expected_token(TokenKind::Term, self.peek()?));
+        }
+        Ok((names, end))
+    }
+
+    fn parse_expr(&mut self) -> Result<Expr, ParseError> {
+        let token = self.next()?;
+        let id = self.node_id();
+        let mut span = token.span;
+
+        let kind = match token.kind {
+            TokenKind::Int => ExprKind::Int(token.value_int().unwrap_or_default()),
+            TokenKind::Float => ExprKind::Float(token.value_float().unwrap_or_default()),
+            TokenKind::Char => ExprKind::Char(token.value_char().unwrap_or_default()),
+            TokenKind::String => ExprKind::Str(token.value_string().unwrap_or_default()),
+            TokenKind::InterpolatedString => {
+                ExprKind::Interpolated(self.parse_interpolated(token)?)
+            }
+            TokenKind::Term => ExprKind::Term(token.value_string().unwrap_or_default()),
+            TokenKind::Backslash => {
+                let term = self.expect(TokenKind::Term)?;
+                span = span.to(term.span);
+                ExprKind::Quote(term.value_string().unwrap_or_default())
+            }
+            // A key right after the brace makes a table rather than a closure
+            TokenKind::LBrace => {
+                self.skip_layout()?;
+                if self.peek()?.kind == TokenKind::Key {
+                    let (entries, end) = self.parse_table()?;
+                    span = span.to(end);
+                    ExprKind::Table(entries)
+                } else {
+                    let (body, end) = self.parse_closure()?;
+                    span = span.to(end);
+                    ExprKind::Closure(body)
+                }
+            }
+            TokenKind::LBracket => {
+                let (items, end) = self.parse_list()?;
+                span = span.to(end);
+                ExprKind::List(items)
+            }
+            TokenKind::Arrow => {
+                let (names, end) = self.parse_binding(span)?;
+                span = span.to(end);
+                ExprKind::Bind(names)
+            }
+            _ => {
+                return Err(ParseError::new(
+                    Some(token.span),
+                    ParseErrorKind::ExpectedExpression { got: token.kind },
+                ))
+            }
+        };
+
+        Ok(Expr {
+            id,
+            span: self.span(span),
+            kind,
+        })
+    }
+}
+
+fn expected_token(expected: TokenKind, got: &Token) -> ParseError {
+    ParseError::new(
+        Some(got.span),
+        ParseErrorKind::ExpectedToken {
+            expected,
+            got: got.kind,
+        },
+    )
 }
@@ -0,0 +1,116 @@
+use crate::lang::span::Span;
+
+/// Identifies a file of a [`SourceMap`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord, Default)]
+pub struct FileId(pub u32);
+
+/// A 1-based line and column in a source file
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Location {
+    pub line: usize,
+    pub column: usize,
+}
+
+/// A named source text, converts span offsets to lines and columns
+#[derive(Debug, Clone)]
+pub struct SourceFile {
+    pub name: String,
+    pub source: String,
+    line_starts: Vec<usize>,
+}
+
+impl SourceFile {
+    pub fn new(name: impl Into<String>, source: impl Into<String>) -> Self {
+        let source = source.into();
+        let line_starts = std::iter::once(0)
+            .chain(source.match_indices('\n').map(|(idx, _)| idx + 1))
+            .collect();
+
+        Self {
+            name: name.into(),
+            source,
+            line_starts,
+        }
+    }
+
+    pub fn line_count(&self) -> usize {
+        self.line_starts.len()
+    }
+
+    /// Location of a byte offset, columns count chars
+    pub fn location(&self, offset: usize) -> Location {
+        let line = match self.line_starts.binary_search(&offset) {
+            Ok(line) => line,
+            Err(line) => line - 1,
+        };
+        let start = self.line_starts[line];
+        let column = self
+            .source
+            .get(start..offset)
+            .map_or(offset - start, |text| text.chars().count());
+        Location {
+            line: line + 1,
+            column: column + 1,
+        }
+    }
+
+    /// Byte offset of a location, the inverse of [`SourceFile::location`]
+    pub fn offset(&self, location: Location) -> Option<usize> {
+        let start = *self.line_starts.get(location.line.checked_sub(1)?)?;
+        let column = location.column.checked_sub(1)?;
+        let width = self.source[start..]
+            .char_indices()
+            .nth(column)
+            .map_or(self.source.len() - start, |(idx, _)| idx);
+        Some(start + width)
+    }
+
+    pub fn span_location(&self, span: Span) -> Location {
+        self.location(span.start)
+    }
+
+    /// Text of a 1-based line, without its line break
+    pub fn line(&self, line: usize) -> Option<&str> {
+        let idx = line.checked_sub(1)?;
+        let start = *self.line_starts.get(idx)?;
+        let end = self
+            .line_starts
+            .get(idx + 1)
+            .map_or(self.source.len(), |next| next - 1);
+        Some(self.source[start..end].trim_end_matches('\r'))
+    }
+}
+
+/// Owns the sources of a program, spans refer to them by [`FileId`]
+#[derive(Debug, Default)]
+pub struct SourceMap {
+    files: Vec<SourceFile>,
+}
+
+impl SourceMap {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn add(&mut self, name: impl Into<String>, source: impl Into<String>) -> FileId {
+        self.files.push(SourceFile::new(name, source));
+        FileId(self.files.len() as u32 - 1)
+    }
+
+    pub fn file(&self, id: FileId) -> Option<&SourceFile> {
+        self.files.get(id.0 as usize)
+    }
+
+    pub fn files(&self) -> impl Iterator<Item = (FileId, &SourceFile)> {
+        self.files
+            .iter()
+            .enumerate()
+            .map(|(idx, file)| (FileId(idx as u32), file))
+    }
+
+    /// The file a span points into, and the location it starts at
+    pub fn location(&self, span: Span) -> Option<(&SourceFile, Location)> {
+        let file = self.file(span.file)?;
+        Some((file, file.span_location(span)))
+    }
+}
@@ -1,11 +1,39 @@
+use crate::lang::source::FileId;
+
 #[derive(Debug, Clone, Copy, PartialEq, Default)]
 pub struct Span {
+    pub file: FileId,
     pub start: usize,
     pub length: usize,
 }
 
 impl Span {
+    /// A span in the first file of a source map, see [`Span::in_file`]
     pub fn new(start: usize, length: usize) -> Self {
-        Self { start, length }
+        Self {
+            file: FileId::default(),
+            start,
+            length,
+        }
+    }
+
+    pub fn in_file(file: FileId, start: usize, length: usize) -> Self {
+        Self {
+            file,
+            start,
+            length,
+        }
+    }
+
+    pub fn end(&self) -> usize {
+        self.start + self.length
+    }
+
+    /// The span from the start of this one to the end of `other`
+    pub fn to(self, other: Span) -> Span {
+        Span {
+            length: other.end().saturating_sub(self.start),
+            ..self
+        }
     }
 }
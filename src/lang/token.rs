@@ -1,31 +1,47 @@
-use crate::lang::{span::Span, token::TokenKind::Def};
+use crate::lang::span::Span;
 use derive_more::Display;
+use std::borrow::Cow;
 
 #[derive(Debug, Copy, Display, Clone, PartialEq)]
 pub enum TokenKind {
     Def,
+    Import,
     Eq,
+    Arrow,
     Term,
     Char,
     Int,
     Float,
     String,
+    InterpolatedString,
     Indent,
     Dedent,
     LBrace,
     RBrace,
+    LParen,
+    RParen,
+    LBracket,
+    RBracket,
+    Comma,
+    /// A table key, `name:`
+    Key,
     Backslash,
+    /// A `##` comment on a line of its own and not indented, its value is
+    /// the text after the `##`. Lossless lexers keep these as comment trivia
+    DocComment,
     Eof,
 }
 
+/// A token of a source, its text values borrow from the source when they
+/// can
 #[derive(Debug, Clone, PartialEq)]
-pub struct Token {
+pub struct Token<'a> {
     pub span: Span,
     pub kind: TokenKind,
-    pub value: Option<TokenValue>,
+    pub value: Option<TokenValue<'a>>,
 }
 
-impl Token {
+impl<'a> Token<'a> {
     pub fn new(span: Span, kind: TokenKind) -> Self {
         Self {
             span,
@@ -43,7 +59,7 @@ impl Token {
     pub fn with_span(self, span: Span) -> Self {
         Self { span, ..self }
     }
-    pub fn with(kind: TokenKind, value: TokenValue) -> Self {
+    pub fn with(kind: TokenKind, value: TokenValue<'a>) -> Self {
         Self {
             span: Default::default(),
             kind,
@@ -53,8 +69,8 @@ impl Token {
     pub fn with_char(kind: TokenKind, value: char) -> Self {
         Self::with(kind, TokenValue::Char(value))
     }
-    pub fn with_string(kind: TokenKind, value: String) -> Self {
-        Self::with(kind, TokenValue::String(value))
+    pub fn with_string(kind: TokenKind, value: impl Into<Cow<'a, str>>) -> Self {
+        Self::with(kind, TokenValue::String(value.into()))
     }
     pub fn with_int(kind: TokenKind, value: i64) -> Self {
         Self::with(kind, TokenValue::Int(value))
@@ -70,13 +86,17 @@ impl Token {
         }
     }
 
-    pub fn value_string(&self) -> Option<String> {
+    pub fn value_str(&self) -> Option<&str> {
         match &self.value {
-            Some(TokenValue::String(v)) => Some(v.clone()),
+            Some(TokenValue::String(v)) => Some(v),
             _ => None,
         }
     }
 
+    pub fn value_string(&self) -> Option<String> {
+        self.value_str().map(str::to_string)
+    }
+
     pub fn value_int(&self) -> Option<i64> {
         match &self.value {
             Some(TokenValue::Int(v)) => Some(*v),
@@ -90,12 +110,31 @@ impl Token {
             _ => None,
         }
     }
+
+    pub fn value_parts(&self) -> Option<Vec<StringPart<'a>>> {
+        match &self.value {
+            Some(TokenValue::Parts(v)) => Some(v.clone()),
+            _ => None,
+        }
+    }
 }
 
 #[derive(Debug, Clone, PartialEq)]
-pub enum TokenValue {
+pub enum TokenValue<'a> {
     Char(char),
-    String(String),
+    String(Cow<'a, str>),
     Int(i64),
     Float(f64),
+    Parts(Vec<StringPart<'a>>),
+}
+
+/// A piece of an interpolated string
+#[derive(Debug, Clone, PartialEq)]
+pub enum StringPart<'a> {
+    Literal(Cow<'a, str>),
+    /// Source of an interpolated `{...}`, and the offset of its first byte
+    Code {
+        source: &'a str,
+        offset: usize,
+    },
 }
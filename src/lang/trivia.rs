@@ -0,0 +1,110 @@
+use crate::lang::{
+    lexer::Lexer,
+    parser::ParseError,
+    span::Span,
+    token::{Token, TokenKind},
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TriviaKind {
+    Whitespace,
+    Newline,
+    Comment,
+}
+
+/// Source text that carries no meaning for the parser
+#[derive(Debug, Clone, PartialEq)]
+pub struct Trivia<'a> {
+    pub kind: TriviaKind,
+    pub span: Span,
+    pub text: &'a str,
+}
+
+/// A token with its exact text and the trivia around it.
+///
+/// Trailing trivia runs up to and including the end of the token's line,
+/// everything else before a token is leading trivia. Indent and dedent
+/// tokens have an empty text and no trivia.
+#[derive(Debug, Clone, PartialEq)]
+pub struct LosslessToken<'a> {
+    pub token: Token<'a>,
+    pub text: &'a str,
+    pub leading: Vec<Trivia<'a>>,
+    pub trailing: Vec<Trivia<'a>>,
+}
+
+impl LosslessToken<'_> {
+    pub fn kind(&self) -> TokenKind {
+        self.token.kind
+    }
+
+    fn ends_line(&self) -> bool {
+        self.trailing.iter().any(|t| t.kind == TriviaKind::Newline)
+    }
+
+    pub fn write_source(&self, out: &mut String) {
+        for trivia in &self.leading {
+            out.push_str(trivia.text);
+        }
+        out.push_str(self.text);
+        for trivia in &self.trailing {
+            out.push_str(trivia.text);
+        }
+    }
+}
+
+/// Lexes the whole source, keeping every character either in a token or
+/// in trivia. The last token is always `Eof`
+pub fn tokenize_lossless(source: &str) -> Result<Vec<LosslessToken<'_>>, ParseError> {
+    let mut lexer = Lexer::lossless(source);
+    let mut tokens = vec![];
+    let mut trivia = vec![];
+
+    while let Some(token) = lexer.next() {
+        tokens.push(token?);
+        trivia.extend(lexer.take_trivia());
+    }
+
+    let mut result: Vec<LosslessToken> = vec![];
+    let mut pending = vec![];
+    let mut last_real: Option<usize> = None;
+    let mut trivia = trivia.into_iter().peekable();
+
+    for token in tokens {
+        while let Some(t) = trivia.next_if(|t| t.span.start < token.span.start) {
+            match last_real.map(|idx| &mut result[idx]) {
+                Some(last) if pending.is_empty() && !last.ends_line() => last.trailing.push(t),
+                _ => pending.push(t),
+            }
+        }
+
+        let start = token.span.start;
+        let end = start + token.span.length;
+        // Indent and dedent don't take trivia, it goes to the following token
+        let layout = matches!(token.kind, TokenKind::Indent | TokenKind::Dedent);
+        if !layout {
+            last_real = Some(result.len());
+        }
+        result.push(LosslessToken {
+            text: &source[start..end],
+            token,
+            leading: if layout {
+                vec![]
+            } else {
+                std::mem::take(&mut pending)
+            },
+            trailing: vec![],
+        });
+    }
+
+    Ok(result)
+}
+
+/// Rebuilds the source text from lossless tokens
+pub fn to_source(tokens: &[LosslessToken]) -> String {
+    let mut out = String::new();
+    for token in tokens {
+        token.write_source(&mut out);
+    }
+    out
+}
@@ -1,5 +1,8 @@
-#![feature(result_flattening)]
 #![feature(fn_traits)]
 
+pub mod compiler;
 pub mod lang;
+pub mod lsp;
+pub mod repl;
+pub mod trace;
 pub mod vm;
@@ -0,0 +1,111 @@
+use crate::lang::{
+    ast::Ast,
+    lexer::Lexer,
+    parser::{ParseError, Parser},
+    source::SourceFile,
+    span::Span,
+    token::TokenKind,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SymbolKind {
+    Definition,
+    Reference,
+}
+
+/// A word of a document, either the name of a definition or a use of a term
+#[derive(Debug, Clone, PartialEq)]
+pub struct Symbol {
+    pub name: String,
+    pub span: Span,
+    pub kind: SymbolKind,
+}
+
+impl Symbol {
+    fn contains(&self, offset: usize) -> bool {
+        (self.span.start..=self.span.start + self.span.length).contains(&offset)
+    }
+}
+
+/// What the server knows about an open document
+#[derive(Debug)]
+pub struct Analysis {
+    pub file: SourceFile,
+    pub ast: Ast,
+    pub errors: Vec<ParseError>,
+    pub symbols: Vec<Symbol>,
+}
+
+impl Analysis {
+    pub fn new(name: impl Into<String>, source: impl Into<String>) -> Self {
+        let file = SourceFile::new(name, source);
+        let (ast, errors) = Parser::new(&file.source).parse_with_recovery();
+        let symbols = symbols(&file.source);
+
+        Self {
+            file,
+            ast,
+            errors,
+            symbols,
+        }
+    }
+
+    pub fn symbol_at(&self, offset: usize) -> Option<&Symbol> {
+        self.symbols.iter().find(|symbol| symbol.contains(offset))
+    }
+
+    pub fn definition(&self, name: &str) -> Option<&Symbol> {
+        self.definitions().find(|symbol| symbol.name == name)
+    }
+
+    pub fn definitions(&self) -> impl Iterator<Item = &Symbol> {
+        self.symbols
+            .iter()
+            .filter(|symbol| symbol.kind == SymbolKind::Definition)
+    }
+
+    pub fn references<'a>(&'a self, name: &'a str) -> impl Iterator<Item = &'a Symbol> {
+        self.symbols
+            .iter()
+            .filter(move |symbol| symbol.name == name)
+    }
+
+    /// The `##` comment lines right above a definition, as parsed
+    pub fn doc(&self, definition: &Symbol) -> Option<String> {
+        self.ast
+            .definitions
+            .iter()
+            .find(|it| it.name == definition.name)
+            .and_then(|it| it.doc.clone())
+    }
+}
+
+/// Collects the words of the source, lexing resumes past errors so a
+/// document being edited still has its symbols
+fn symbols(source: &str) -> Vec<Symbol> {
+    let mut symbols = vec![];
+    let mut previous = TokenKind::Eof;
+    // Names of a stack effect are only documentation
+    let mut in_effect = false;
+
+    for token in Lexer::new(source).flatten() {
+        match token.kind {
+            TokenKind::LParen => in_effect = true,
+            TokenKind::RParen => in_effect = false,
+            TokenKind::Term if previous != TokenKind::Import && !in_effect => {
+                symbols.push(Symbol {
+                    name: token.value_string().unwrap_or_default(),
+                    span: token.span,
+                    kind: if previous == TokenKind::Def {
+                        SymbolKind::Definition
+                    } else {
+                        SymbolKind::Reference
+                    },
+                })
+            }
+            _ => {}
+        }
+        previous = token.kind;
+    }
+    symbols
+}
@@ -0,0 +1,290 @@
+use crate::{
+    compiler::builtins::{find_builtin, BUILTINS},
+    lang::{
+        source::{Location, SourceFile},
+        span::Span,
+    },
+    lsp::analysis::{Analysis, SymbolKind},
+};
+use serde_json::{json, Value};
+use std::collections::HashMap;
+
+pub mod analysis;
+pub mod transport;
+
+const INVALID_PARAMS: i64 = -32602;
+const METHOD_NOT_FOUND: i64 = -32601;
+
+const SEVERITY_ERROR: i64 = 1;
+const COMPLETION_FUNCTION: i64 = 3;
+const COMPLETION_KEYWORD: i64 = 14;
+// Documents are sent in full on every change
+const SYNC_FULL: i64 = 1;
+
+#[derive(Debug)]
+struct ResponseError {
+    code: i64,
+    message: String,
+}
+
+impl ResponseError {
+    fn new(code: i64, message: impl Into<String>) -> Self {
+        Self {
+            code,
+            message: message.into(),
+        }
+    }
+}
+
+/// A language server over the documents the client has opened. Positions
+/// are exchanged as lines and UTF-16 code units, as the protocol requires
+#[derive(Debug, Default)]
+pub struct Server {
+    documents: HashMap<String, Analysis>,
+    shutdown: bool,
+    exited: bool,
+}
+
+impl Server {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Set once the client sent `exit`, the process should then stop
+    pub fn exited(&self) -> bool {
+        self.exited
+    }
+
+    /// Whether `exit` was preceded by a `shutdown` request
+    pub fn shutdown(&self) -> bool {
+        self.shutdown
+    }
+
+    /// Handles a message from the client, returns the messages to send back
+    pub fn handle(&mut self, message: &Value) -> Vec<Value> {
+        let method = message["method"].as_str().unwrap_or_default();
+        let params = &message["params"];
+
+        match message.get("id") {
+            Some(id) => {
+                let response = match self.request(method, params) {
+                    Ok(result) => json!({ "jsonrpc": "2.0", "id": id, "result": result }),
+                    Err(error) => json!({
+                        "jsonrpc": "2.0",
+                        "id": id,
+                        "error": { "code": error.code, "message": error.message },
+                    }),
+                };
+                vec![response]
+            }
+            None => self.notification(method, params),
+        }
+    }
+
+    fn request(&mut self, method: &str, params: &Value) -> Result<Value, ResponseError> {
+        match method {
+            "initialize" => Ok(json!({
+                "capabilities": {
+                    "textDocumentSync": SYNC_FULL,
+                    "definitionProvider": true,
+                    "referencesProvider": true,
+                    "hoverProvider": true,
+                    "completionProvider": {},
+                },
+                "serverInfo": { "name": "mana-lsp" },
+            })),
+            "shutdown" => {
+                self.shutdown = true;
+                Ok(Value::Null)
+            }
+            "textDocument/definition" => self.definition(params),
+            "textDocument/references" => self.references(params),
+            "textDocument/hover" => self.hover(params),
+            "textDocument/completion" => self.completion(params),
+            _ => Err(ResponseError::new(
+                METHOD_NOT_FOUND,
+                format!("Unknown method '{}'", method),
+            )),
+        }
+    }
+
+    fn notification(&mut self, method: &str, params: &Value) -> Vec<Value> {
+        let uri = params["textDocument"]["uri"].as_str().unwrap_or_default();
+        match method {
+            "textDocument/didOpen" => {
+                let text = params["textDocument"]["text"].as_str().unwrap_or_default();
+                self.open(uri, text)
+            }
+            "textDocument/didChange" => {
+                let text = params["contentChanges"]
+                    .as_array()
+                    .and_then(|changes| changes.last())
+                    .and_then(|change| change["text"].as_str())
+                    .unwrap_or_default();
+                self.open(uri, text)
+            }
+            "textDocument/didClose" => {
+                self.documents.remove(uri);
+                vec![publish_diagnostics(uri, vec![])]
+            }
+            "exit" => {
+                self.exited = true;
+                vec![]
+            }
+            _ => vec![],
+        }
+    }
+
+    fn open(&mut self, uri: &str, text: &str) -> Vec<Value> {
+        let analysis = Analysis::new(uri, text);
+        let diagnostics = analysis
+            .errors
+            .iter()
+            .map(|error| {
+                json!({
+                    "range": range(&analysis.file, error.span().unwrap_or_default()),
+                    "severity": SEVERITY_ERROR,
+                    "source": "mana",
+                    "message": error.to_string(),
+                })
+            })
+            .collect();
+        self.documents.insert(uri.to_string(), analysis);
+        vec![publish_diagnostics(uri, diagnostics)]
+    }
+
+    /// The document and offset a position request points at
+    fn document(&self, params: &Value) -> Result<(&str, &Analysis, usize), ResponseError> {
+        let uri = params["textDocument"]["uri"].as_str().unwrap_or_default();
+        let (uri, document) = self
+            .documents
+            .get_key_value(uri)
+            .ok_or_else(|| ResponseError::new(INVALID_PARAMS, format!("'{}' is not open", uri)))?;
+
+        let offset = offset(&document.file, &params["position"])
+            .ok_or_else(|| ResponseError::new(INVALID_PARAMS, "Position out of the document"))?;
+
+        Ok((uri, document, offset))
+    }
+
+    fn definition(&self, params: &Value) -> Result<Value, ResponseError> {
+        let (uri, document, offset) = self.document(params)?;
+        let definition = document
+            .symbol_at(offset)
+            .and_then(|symbol| document.definition(&symbol.name));
+
+        Ok(match definition {
+            Some(definition) => location(uri, &document.file, definition.span),
+            None => Value::Null,
+        })
+    }
+
+    fn references(&self, params: &Value) -> Result<Value, ResponseError> {
+        let (uri, document, offset) = self.document(params)?;
+        let include_declaration = params["context"]["includeDeclaration"]
+            .as_bool()
+            .unwrap_or(true);
+        let Some(symbol) = document.symbol_at(offset) else {
+            return Ok(Value::Null);
+        };
+
+        let locations = document
+            .references(&symbol.name)
+            .filter(|it| include_declaration || it.kind == SymbolKind::Reference)
+            .map(|it| location(uri, &document.file, it.span))
+            .collect();
+        Ok(Value::Array(locations))
+    }
+
+    fn hover(&self, params: &Value) -> Result<Value, ResponseError> {
+        let (_, document, offset) = self.document(params)?;
+        let Some(symbol) = document.symbol_at(offset) else {
+            return Ok(Value::Null);
+        };
+
+        let contents = if let Some(definition) = document.definition(&symbol.name) {
+            let mut contents = format!("```mana\ndef {}\n```", definition.name);
+            if let Some(doc) = document.doc(definition) {
+                contents.push_str("\n\n");
+                contents.push_str(&doc);
+            }
+            contents
+        } else if let Some(builtin) = find_builtin(&symbol.name) {
+            format!(
+                "```mana\n{}\n```\n\nBuiltin `{:?}`",
+                builtin.name, builtin.inst
+            )
+        } else {
+            return Ok(Value::Null);
+        };
+
+        Ok(json!({
+            "contents": { "kind": "markdown", "value": contents },
+            "range": range(&document.file, symbol.span),
+        }))
+    }
+
+    fn completion(&self, params: &Value) -> Result<Value, ResponseError> {
+        let (_, document, _) = self.document(params)?;
+
+        let definitions = document.definitions().map(|definition| {
+            json!({
+                "label": definition.name,
+                "kind": COMPLETION_FUNCTION,
+                "documentation": document.doc(definition),
+            })
+        });
+        let builtins = BUILTINS.iter().map(|builtin| {
+            json!({
+                "label": builtin.name,
+                "kind": COMPLETION_KEYWORD,
+                "detail": "builtin",
+            })
+        });
+        Ok(Value::Array(definitions.chain(builtins).collect()))
+    }
+}
+
+fn publish_diagnostics(uri: &str, diagnostics: Vec<Value>) -> Value {
+    json!({
+        "jsonrpc": "2.0",
+        "method": "textDocument/publishDiagnostics",
+        "params": { "uri": uri, "diagnostics": diagnostics },
+    })
+}
+
+/// Positions count UTF-16 code units from the start of the line
+fn position(file: &SourceFile, offset: usize) -> Value {
+    let line = file.location(offset).line;
+    let start = file.offset(Location { line, column: 1 }).unwrap_or(offset);
+    let character = file.source[start..offset].encode_utf16().count();
+    json!({ "line": line - 1, "character": character })
+}
+
+/// Byte offset of a position, the inverse of [`position`]. Characters past
+/// the end of the line point at its end
+fn offset(file: &SourceFile, position: &Value) -> Option<usize> {
+    let line = position["line"].as_u64().unwrap_or_default() as usize + 1;
+    let character = position["character"].as_u64().unwrap_or_default() as usize;
+    let start = file.offset(Location { line, column: 1 })?;
+
+    let mut units = 0;
+    for (idx, c) in file.source[start..].char_indices() {
+        if units >= character || c == '\n' {
+            return Some(start + idx);
+        }
+        units += c.len_utf16();
+    }
+    Some(file.source.len())
+}
+
+fn range(file: &SourceFile, span: Span) -> Value {
+    json!({
+        "start": position(file, span.start),
+        "end": position(file, span.start + span.length),
+    })
+}
+
+fn location(uri: &str, file: &SourceFile, span: Span) -> Value {
+    json!({ "uri": uri, "range": range(file, span) })
+}
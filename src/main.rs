@@ -1,31 +1,184 @@
-use mana::lang::{
-    lexer::Lexer,
-    token::{Token, TokenKind},
+use mana::{
+    compiler::compile_program,
+    lang::{
+        diagnostic::Diagnostic,
+        doc, formatter,
+        loader::Loader,
+        parser::Parser,
+        source::{FileId, SourceMap},
+    },
+    repl::Repl,
 };
+use std::{
+    env, fs,
+    io::{self, BufRead, Write},
+    process::ExitCode,
+};
+
+const USAGE: &str = "Usage:
+    mana check <file>...
+    mana fmt [--check] <file>...
+    mana doc [--html] <file>
+    mana repl";
+
+fn main() -> ExitCode {
+    let args: Vec<String> = env::args().skip(1).collect();
+
+    match args.split_first() {
+        Some((command, files)) if command == "check" && !files.is_empty() => check(files),
+        Some((command, [flag, files @ ..])) if command == "fmt" && flag == "--check" => {
+            fmt(files, true)
+        }
+        Some((command, files)) if command == "fmt" && !files.is_empty() => fmt(files, false),
+        Some((command, [flag, file])) if command == "doc" && flag == "--html" => docs(file, true),
+        Some((command, [file])) if command == "doc" => docs(file, false),
+        Some((command, [])) if command == "repl" => repl(),
+        _ => usage(),
+    }
+}
+
+fn usage() -> ExitCode {
+    eprintln!("{}", USAGE);
+    ExitCode::FAILURE
+}
+
+/// Reads a file into the source map, returns its id and content
+fn read(sources: &mut SourceMap, path: &str) -> Option<(FileId, String)> {
+    match fs::read_to_string(path) {
+        Ok(source) => Some((sources.add(path, source.clone()), source)),
+        Err(e) => {
+            eprintln!("error: Could not read '{}': {}", path, e);
+            None
+        }
+    }
+}
+
+fn exit_code(failed: bool) -> ExitCode {
+    if failed {
+        ExitCode::FAILURE
+    } else {
+        ExitCode::SUCCESS
+    }
+}
+
+/// Reports every syntax error of the given files. Files without any are
+/// loaded with their imports and compiled, the first error is reported
+fn check(files: &[String]) -> ExitCode {
+    let mut sources = SourceMap::new();
+    let mut failed = false;
 
-fn main() {
-    let source = r#"
-def fact2 =
-    countTo       # produce a range from 0 to n (not included)
-    \* reduce   # consume an seq and calculate the product of its elements
-"#;
-
-    let mut lexer = Lexer::new(source);
-
-    loop {
-        match lexer.next() {
-            Ok(Token {
-                kind: TokenKind::Eof,
-                ..
-            }) => {
-                println!("EOF");
-                break;
+    for path in files {
+        let Some((file, source)) = read(&mut sources, path) else {
+            failed = true;
+            continue;
+        };
+
+        let (_, errors) = Parser::new(&source).with_file(file).parse_with_recovery();
+        for error in &errors {
+            eprintln!("{}", Diagnostic::from(error).render(&sources));
+        }
+        if !errors.is_empty() {
+            failed = true;
+            continue;
+        }
+
+        let program = match Loader::new().load(&mut sources, path) {
+            Ok(program) => program,
+            Err(error) => {
+                eprintln!("{}", error.render(&sources));
+                failed = true;
+                continue;
             }
-            Ok(t) => {} //println!("{:?}", t)
-            Err(e) => {
-                println!("{:?}", e);
-                break;
+        };
+        if let Err(error) = compile_program(&program) {
+            eprintln!("{}", Diagnostic::from(&error).render(&sources));
+            failed = true;
+        }
+    }
+
+    exit_code(failed)
+}
+
+/// Rewrites the given files in the canonical layout. With `--check` files
+/// are left untouched, and the ones that aren't formatted are reported
+fn fmt(files: &[String], check: bool) -> ExitCode {
+    if files.is_empty() {
+        return usage();
+    }
+    let mut failed = false;
+
+    for path in files {
+        // Files are formatted on their own, each is the only one of its map
+        let mut sources = SourceMap::new();
+        let Some((_, source)) = read(&mut sources, path) else {
+            failed = true;
+            continue;
+        };
+
+        let formatted = match formatter::format(&source) {
+            Ok(formatted) => formatted,
+            Err(error) => {
+                eprintln!("{}", Diagnostic::from(&error).render(&sources));
+                failed = true;
+                continue;
+            }
+        };
+        if formatted == source {
+            continue;
+        }
+
+        if check {
+            eprintln!("{} is not formatted", path);
+            failed = true;
+        } else if let Err(e) = fs::write(path, formatted) {
+            eprintln!("error: Could not write '{}': {}", path, e);
+            failed = true;
+        }
+    }
+
+    exit_code(failed)
+}
+
+/// Prints a reference of a module and the modules it imports, as Markdown
+/// or with `--html` as an HTML page
+fn docs(path: &str, html: bool) -> ExitCode {
+    let mut sources = SourceMap::new();
+    let program = match Loader::new().load(&mut sources, path) {
+        Ok(program) => program,
+        Err(error) => {
+            eprintln!("{}", error.render(&sources));
+            return ExitCode::FAILURE;
+        }
+    };
+
+    if html {
+        print!("{}", doc::html(&program));
+    } else {
+        print!("{}", doc::markdown(&program));
+    }
+    ExitCode::SUCCESS
+}
+
+/// Reads lines from stdin until `:quit` or the end of the input
+fn repl() -> ExitCode {
+    let mut repl = Repl::new();
+    let mut stdout = io::stdout();
+    let mut lines = io::stdin().lock().lines();
+
+    while !repl.exited() {
+        print!("{}", repl.prompt());
+        let _ = stdout.flush();
+        let line = match lines.next() {
+            Some(Ok(line)) => line,
+            Some(Err(e)) => {
+                eprintln!("error: Could not read the input: {}", e);
+                return ExitCode::FAILURE;
             }
+            None => break,
+        };
+        if let Some(output) = repl.line(&line) {
+            print!("{}", output);
         }
     }
+    ExitCode::SUCCESS
 }
@@ -0,0 +1,222 @@
+use crate::{
+    compiler::{compile, reserved_name},
+    lang::{
+        ast::{Ast, Definition},
+        diagnostic::Diagnostic,
+        lexer::Lexer,
+        parser::Parser,
+        source::SourceMap,
+        token::TokenKind,
+    },
+    vm::VM,
+};
+use std::fmt::Write;
+
+/// Name of the function running the code of an input line
+fn entry() -> String {
+    reserved_name("", "repl")
+}
+
+const HELP: &str = "\
+:stack         show the stack
+:clear         empty the stack
+:disasm word   show the instructions of a definition
+:trace on|off  print every executed instruction
+:history       show the previous inputs
+:quit          leave the repl
+";
+
+/// Runs input lines against a single VM, whose stack lives on between
+/// lines. Definitions are kept and recompiled with every input, defining a
+/// word again replaces it.
+///
+/// A definition, or input with unclosed brackets, continues on the
+/// following lines. Definitions end with a blank line
+pub struct Repl {
+    vm: VM<'static>,
+    sources: SourceMap,
+    definitions: Vec<Definition>,
+    buffer: String,
+    history: Vec<String>,
+    exited: bool,
+}
+
+impl Default for Repl {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Repl {
+    pub fn new() -> Self {
+        Self {
+            vm: VM::new(Default::default()),
+            sources: SourceMap::new(),
+            definitions: vec![],
+            buffer: String::new(),
+            history: vec![],
+            exited: false,
+        }
+    }
+
+    pub fn prompt(&self) -> &'static str {
+        if self.buffer.is_empty() {
+            "> "
+        } else {
+            "... "
+        }
+    }
+
+    pub fn exited(&self) -> bool {
+        self.exited
+    }
+
+    /// Previous inputs, oldest first. Inputs spanning several lines are
+    /// kept whole
+    pub fn history(&self) -> &[String] {
+        &self.history
+    }
+
+    /// Reads a line of input. Returns what to print once the input is
+    /// complete, None while it continues on the next line
+    pub fn line(&mut self, line: &str) -> Option<String> {
+        if self.buffer.is_empty() {
+            if line.trim().is_empty() {
+                return Some(String::new());
+            }
+            if let Some(command) = line.trim().strip_prefix(':') {
+                self.history.push(line.trim().to_string());
+                return Some(self.command(command));
+            }
+        }
+
+        self.buffer.push_str(line);
+        self.buffer.push('\n');
+        if !self.is_complete() {
+            return None;
+        }
+        let input = std::mem::take(&mut self.buffer);
+        self.history.push(input.trim_end().to_string());
+        Some(self.eval(&input))
+    }
+
+    fn is_complete(&self) -> bool {
+        let lines: Vec<&str> = self.buffer.lines().collect();
+        if lines.last().is_some_and(|line| line.trim().is_empty()) {
+            return true;
+        }
+        let depth = Lexer::new(&self.buffer)
+            .flatten()
+            .fold(0, |depth: i32, token| match token.kind {
+                TokenKind::LBrace | TokenKind::LBracket | TokenKind::LParen => depth + 1,
+                TokenKind::RBrace | TokenKind::RBracket | TokenKind::RParen => depth - 1,
+                _ => depth,
+            });
+        let block = lines.first().is_some_and(|line| line.starts_with("def "));
+        depth <= 0 && !block
+    }
+
+    fn command(&mut self, command: &str) -> String {
+        let (name, argument) = command.split_once(' ').unwrap_or((command, ""));
+        match (name, argument.trim()) {
+            ("stack", "") => format!("{}\n", self.vm.stack()),
+            ("clear", "") => {
+                self.vm.clear();
+                format!("{}\n", self.vm.stack())
+            }
+            ("disasm", word) if !word.is_empty() => self.disasm(word),
+            ("trace", "on") => {
+                self.vm.tracing(true);
+                String::new()
+            }
+            ("trace", "off") => {
+                self.vm.tracing(false);
+                String::new()
+            }
+            ("history", "") => {
+                let mut out = String::new();
+                for (idx, input) in self.history.iter().enumerate() {
+                    let _ = writeln!(out, "{:>4}  {}", idx + 1, input.replace('\n', "\n      "));
+                }
+                out
+            }
+            ("help", "") => HELP.to_string(),
+            ("quit", "") => {
+                self.exited = true;
+                String::new()
+            }
+            _ => format!("error: Unknown command ':{}', see :help\n", command),
+        }
+    }
+
+    fn disasm(&self, word: &str) -> String {
+        let Some(function) = self.vm.function(word) else {
+            return format!("error: Unknown word '{}'\n", word);
+        };
+        let mut out = String::new();
+        for (addr, instruction) in function.instructions.iter().enumerate() {
+            let _ = writeln!(out, "{:>4}  {:?}", addr, instruction);
+        }
+        out
+    }
+
+    /// Compiles the input along with the previous definitions, and runs it
+    /// unless it only defines words
+    fn eval(&mut self, input: &str) -> String {
+        let first = Lexer::new(input)
+            .flatten()
+            .map(|token| token.kind)
+            .find(|kind| !matches!(kind, TokenKind::Indent | TokenKind::Dedent));
+        let defines = match first {
+            Some(TokenKind::Def) => true,
+            Some(TokenKind::Import) => {
+                return "error: Imports are not supported in the repl\n".to_string()
+            }
+            _ => false,
+        };
+
+        let file = self
+            .sources
+            .add(format!("<repl {}>", self.history.len()), input);
+        let mut parser = Parser::new(input).with_file(file);
+        // Code is the body of an entry definition
+        let parsed = if defines {
+            parser.parse()
+        } else {
+            parser.parse_code(entry()).map(|definition| Ast {
+                imports: vec![],
+                definitions: vec![definition],
+            })
+        };
+        let ast = match parsed {
+            Ok(ast) => ast,
+            Err(error) => return Diagnostic::from(&error).render(&self.sources),
+        };
+
+        let mut definitions: Vec<Definition> = self
+            .definitions
+            .iter()
+            .filter(|old| !ast.definitions.iter().any(|new| new.name == old.name))
+            .cloned()
+            .collect();
+        definitions.extend(ast.definitions);
+        let program = Ast {
+            imports: vec![],
+            definitions,
+        };
+        let functions = match compile(&program) {
+            Ok(functions) => functions,
+            Err(error) => return Diagnostic::from(&error).render(&self.sources),
+        };
+
+        self.vm.load(functions);
+        let mut out = String::new();
+        if defines {
+            self.definitions = program.definitions;
+        } else if let Err(error) = self.vm.run(entry()) {
+            out.push_str(&error.render(&self.sources));
+        }
+        let _ = writeln!(out, "{}", self.vm.stack());
+        out
+    }
+}
@@ -0,0 +1,134 @@
+use crate::{
+    lang::token::Token,
+    vm::{env::Env, instructions::Inst, stack::Stack},
+};
+use serde_json::{json, Value};
+use std::{
+    error::Error,
+    fmt::Debug,
+    io::{self, Write},
+};
+
+/// Something that happened while lexing or running a program
+#[derive(Debug, Clone, Copy)]
+pub enum Event<'e> {
+    /// The lexer produced a token
+    Token(&'e Token<'e>),
+    /// The VM executed an instruction, leaving the given stack and locals
+    Instruction {
+        function: &'e str,
+        addr: usize,
+        instruction: &'e Inst,
+        stack: &'e Stack,
+        env: &'e Env,
+    },
+    /// The VM started executing a function
+    Call { function: &'e str },
+    /// The VM finished executing a function
+    Return { function: &'e str },
+    /// Lexing or execution failed
+    Error(&'e dyn Error),
+}
+
+/// Observes the lexer and the VM, see [`Event`]
+pub trait Tracer: Debug {
+    fn trace(&mut self, event: &Event);
+}
+
+impl<T: Tracer + ?Sized> Tracer for &mut T {
+    fn trace(&mut self, event: &Event) {
+        (**self).trace(event)
+    }
+}
+
+/// Ignores every event, the default tracer
+#[derive(Debug, Default, Clone, Copy)]
+pub struct NullTracer;
+
+impl Tracer for NullTracer {
+    fn trace(&mut self, _: &Event) {}
+}
+
+/// Prints events to stdout for a person to read
+#[derive(Debug, Default, Clone, Copy)]
+pub struct StdoutTracer;
+
+impl Tracer for StdoutTracer {
+    fn trace(&mut self, event: &Event) {
+        match event {
+            Event::Token(token) => println!("{:?}", token),
+            Event::Instruction {
+                function,
+                addr,
+                instruction,
+                stack,
+                env,
+            } => {
+                println!("-------------");
+                println!("{}@{}: {:?}", function, addr, instruction);
+                println!("Stack: {}", stack);
+                println!("Env: {}", env);
+            }
+            Event::Call { function } => println!("-> {}", function),
+            Event::Return { function } => println!("<- {}", function),
+            Event::Error(error) => println!("error: {}", error),
+        }
+    }
+}
+
+/// Writes each event as a line of JSON, for tools to consume
+#[derive(Debug)]
+pub struct JsonTracer<W: Write + Debug> {
+    out: W,
+}
+
+impl<W: Write + Debug> JsonTracer<W> {
+    pub fn new(out: W) -> Self {
+        Self { out }
+    }
+
+    pub fn into_inner(self) -> W {
+        self.out
+    }
+}
+
+impl JsonTracer<io::Stdout> {
+    pub fn stdout() -> Self {
+        Self::new(io::stdout())
+    }
+}
+
+impl<W: Write + Debug> Tracer for JsonTracer<W> {
+    fn trace(&mut self, event: &Event) {
+        // A tracer must not fail the traced program, lost lines are dropped
+        let _ = writeln!(self.out, "{}", to_json(event));
+    }
+}
+
+fn to_json(event: &Event) -> Value {
+    match event {
+        Event::Token(token) => json!({
+            "event": "token",
+            "kind": token.kind.to_string(),
+            "start": token.span.start,
+            "length": token.span.length,
+        }),
+        Event::Instruction {
+            function,
+            addr,
+            instruction,
+            stack,
+            env,
+        } => json!({
+            "event": "instruction",
+            "function": function,
+            "addr": addr,
+            "instruction": format!("{:?}", instruction),
+            "stack": stack.to_string(),
+            "env": env.to_string(),
+        }),
+        Event::Call { function } => json!({ "event": "call", "function": function }),
+        Event::Return { function } => json!({ "event": "return", "function": function }),
+        Event::Error(error) => json!({ "event": "error", "message": error.to_string() }),
+    }
+}
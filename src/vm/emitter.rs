@@ -1,7 +1,22 @@
-use crate::vm::{function::Function, instructions::Inst};
+use crate::{
+    lang::span::Span,
+    vm::{function::Function, instructions::Inst, value::MetaValue},
+};
+
+/// A loop being emitted, with the breaks of the loop enclosing it
+#[must_use]
+pub struct Loop {
+    start: usize,
+    breaks_if: Vec<usize>,
+    breaks_if_not: Vec<usize>,
+}
 
+#[derive(Default)]
 pub struct Emitter {
     instructions: Vec<Inst>,
+    // Source of each instruction, and of the ones emitted next
+    spans: Vec<Option<Span>>,
+    span: Option<Span>,
     env_locals: usize,
     locals: usize,
     breaks_if: Vec<usize>,
@@ -12,6 +27,8 @@ impl Emitter {
     pub fn new() -> Self {
         Self {
             instructions: vec![],
+            spans: vec![],
+            span: None,
             env_locals: 0,
             locals: 0,
             breaks_if: vec![],
@@ -22,6 +39,8 @@ impl Emitter {
     pub fn with_env(env_locals: usize) -> Self {
         Self {
             instructions: vec![],
+            spans: vec![],
+            span: None,
             env_locals,
             locals: 0,
             breaks_if: vec![],
@@ -29,8 +48,15 @@ impl Emitter {
         }
     }
 
+    /// Attributes the instructions emitted from now on to a source span
+    pub fn at(&mut self, span: Span) -> &mut Self {
+        self.span = Some(span);
+        self
+    }
+
     pub fn emit(&mut self, inst: Inst) {
-        self.instructions.push(inst)
+        self.instructions.push(inst);
+        self.spans.push(self.span);
     }
 
     pub fn previous_idx(&self) -> usize {
@@ -62,22 +88,37 @@ impl Emitter {
     }
 
     pub fn push_int(&mut self, v: i64) -> &mut Self {
-        self.instructions.push(Inst::PushI(v));
+        self.emit(Inst::PushI(v));
         self
     }
 
     pub fn push_floatt(&mut self, v: f64) -> &mut Self {
-        self.instructions.push(Inst::PushF(v));
+        self.emit(Inst::PushF(v));
+        self
+    }
+
+    pub fn push_str(&mut self, v: impl Into<String>) -> &mut Self {
+        self.emit(Inst::PushS(v.into()));
         self
     }
 
     pub fn push_list(&mut self) -> &mut Self {
-        self.instructions.push(Inst::PushList);
+        self.emit(Inst::PushList);
+        self
+    }
+
+    pub fn push_table(&mut self) -> &mut Self {
+        self.emit(Inst::PushTable);
+        self
+    }
+
+    pub fn push_value(&mut self, v: MetaValue) -> &mut Self {
+        self.emit(Inst::Push(v));
         self
     }
 
     pub fn push_function_ref(&mut self, name: impl Into<String>) -> &mut Self {
-        self.instructions.push(Inst::PushFn(name.into()));
+        self.emit(Inst::PushFn(name.into()));
         self
     }
 
@@ -96,33 +137,48 @@ impl Emitter {
         self
     }
 
+    pub fn into_str(&mut self) -> &mut Self {
+        self.emit(Inst::IntoStr);
+        self
+    }
+
+    pub fn concat(&mut self) -> &mut Self {
+        self.emit(Inst::Concat);
+        self
+    }
+
     pub fn not(&mut self) -> &mut Self {
         self.emit(Inst::Not);
         self
     }
 
     pub fn list_len(&mut self) -> &mut Self {
-        self.instructions.push(Inst::ListLen);
+        self.emit(Inst::ListLen);
         self
     }
 
     pub fn list_get(&mut self) -> &mut Self {
-        self.instructions.push(Inst::ListGet);
+        self.emit(Inst::ListGet);
         self
     }
 
     pub fn list_set(&mut self) -> &mut Self {
-        self.instructions.push(Inst::ListSet);
+        self.emit(Inst::ListSet);
         self
     }
 
     pub fn list_push(&mut self) -> &mut Self {
-        self.instructions.push(Inst::ListPush);
+        self.emit(Inst::ListPush);
+        self
+    }
+
+    pub fn table_set(&mut self) -> &mut Self {
+        self.emit(Inst::TableSet);
         self
     }
 
     pub fn list_pop(&mut self) -> &mut Self {
-        self.instructions.push(Inst::ListPop);
+        self.emit(Inst::ListPop);
         self
     }
 
@@ -191,22 +247,34 @@ impl Emitter {
         }
     }
 
-    pub fn infinite_loop(&mut self, mut block: impl FnMut(&mut Emitter)) -> &mut Self {
-        let start = self.current_idx();
-        block.call_mut((self,));
-        self.branch(start);
+    /// Starts a loop closed by [`Emitter::end_loop`], the breaks emitted in
+    /// between exit this loop only
+    pub fn begin_loop(&mut self) -> Loop {
+        Loop {
+            start: self.current_idx(),
+            breaks_if: std::mem::take(&mut self.breaks_if),
+            breaks_if_not: std::mem::take(&mut self.breaks_if_not),
+        }
+    }
+
+    pub fn end_loop(&mut self, outer: Loop) -> &mut Self {
+        self.branch(outer.start);
         let break_target = self.current_idx();
-        for b in self.breaks_if.clone() {
+        for b in std::mem::replace(&mut self.breaks_if, outer.breaks_if) {
             self.patch(b, Inst::BranchIf(break_target));
         }
-        self.breaks_if.clear();
-        for b in self.breaks_if_not.clone() {
+        for b in std::mem::replace(&mut self.breaks_if_not, outer.breaks_if_not) {
             self.patch(b, Inst::BranchIfNot(break_target));
         }
-        self.breaks_if_not.clear();
         self
     }
 
+    pub fn infinite_loop(&mut self, mut block: impl FnMut(&mut Emitter)) -> &mut Self {
+        let outer = self.begin_loop();
+        block.call_mut((self,));
+        self.end_loop(outer)
+    }
+
     pub fn while_loop(
         &mut self,
         mut cond: impl FnMut(&mut Emitter),
@@ -219,9 +287,30 @@ impl Emitter {
         })
     }
 
+    /// Pops a bool and runs one of the blocks
+    pub fn if_else(
+        &mut self,
+        mut then: impl FnMut(&mut Emitter),
+        mut otherwise: impl FnMut(&mut Emitter),
+    ) -> &mut Self {
+        self.nop();
+        let branch = self.previous_idx();
+        then.call_mut((self,));
+        self.nop();
+        let skip = self.previous_idx();
+
+        let else_target = self.current_idx();
+        self.patch(branch, Inst::BranchIfNot(else_target));
+        otherwise.call_mut((self,));
+        let end = self.current_idx();
+        self.patch(skip, Inst::Branch(end));
+        self
+    }
+
     pub fn finish(self) -> Function {
         Function {
             instructions: self.instructions,
+            spans: self.spans,
             locals: self.locals,
         }
     }
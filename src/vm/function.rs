@@ -1,4 +1,4 @@
-use crate::vm::instructions::Inst;
+use crate::{lang::span::Span, vm::instructions::Inst};
 use std::collections::HashMap;
 
 pub type Functions = HashMap<String, Function>;
@@ -6,5 +6,13 @@ pub type Functions = HashMap<String, Function>;
 #[derive(Debug, Clone)]
 pub struct Function {
     pub instructions: Vec<Inst>,
+    // Source of each instruction, when compiled from source
+    pub spans: Vec<Option<Span>>,
     pub locals: usize,
 }
+
+impl Function {
+    pub fn span(&self, addr: usize) -> Option<Span> {
+        self.spans.get(addr).copied().flatten()
+    }
+}
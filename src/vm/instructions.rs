@@ -1,4 +1,4 @@
-use crate::vm::value::FunctionRef;
+use crate::vm::value::MetaValue;
 
 #[derive(Debug, Clone)]
 pub enum Inst {
@@ -11,11 +11,17 @@ pub enum Inst {
     PushB(bool),
     PushI(i64),
     PushF(f64),
+    PushS(String),
     PushList,
     PushTable,
     PushFn(String),
+    /// A value built at compile time, such as a constant list
+    Push(MetaValue),
     IntoInt,
     IntoFloat,
+    IntoStr,
+    // Str
+    Concat,
     // List
     ListPush,
     ListPop,
@@ -1,9 +1,13 @@
-use crate::vm::{
-    env::Env,
-    function::{Function, Functions},
-    instructions::Inst,
-    stack::Stack,
-    value::{List, MetaValue, Table, Value},
+use crate::{
+    lang::{diagnostic::Diagnostic, source::SourceMap, span::Span},
+    trace::{Event, NullTracer, StdoutTracer, Tracer},
+    vm::{
+        env::Env,
+        function::{Function, Functions},
+        instructions::Inst,
+        stack::Stack,
+        value::{List, MetaValue, Table, Value},
+    },
 };
 use thiserror::Error;
 
@@ -36,26 +40,78 @@ pub enum RuntimeError {
     KeyNotFound(String),
     #[error("Function not found: {0}")]
     FunctionNotFound(String),
+    #[error("Division by zero")]
+    DivisionByZero,
+}
+
+/// A function being executed, and the source of its current instruction
+#[derive(Debug, Clone, PartialEq)]
+pub struct Frame {
+    pub function: String,
+    pub span: Option<Span>,
+}
+
+/// A runtime error, with the calls that led to it innermost first
+#[derive(Debug, Error, PartialEq)]
+#[error("{source}")]
+pub struct VmError {
+    pub source: RuntimeError,
+    pub trace: Vec<Frame>,
+}
+
+impl VmError {
+    /// Where the error happened, the innermost call with a known source
+    pub fn span(&self) -> Option<Span> {
+        self.trace.iter().find_map(|frame| frame.span)
+    }
+
+    /// Renders the error at its source, with the calls leading to it
+    pub fn render(&self, sources: &SourceMap) -> String {
+        let mut diagnostic = Diagnostic::error(self.source.to_string()).with_span(self.span());
+        for frame in &self.trace {
+            let note = match frame.span.and_then(|span| sources.location(span)) {
+                Some((file, location)) => format!(
+                    "in {} at {}:{}:{}",
+                    frame.function, file.name, location.line, location.column
+                ),
+                None => format!("in {}", frame.function),
+            };
+            diagnostic = diagnostic.with_note(note);
+        }
+        diagnostic.render(sources)
+    }
 }
 
 #[derive(Debug)]
-pub struct VM {
+pub struct VM<'a> {
     stack: Stack,
     functions: Functions,
-    tracing: bool,
+    // Functions being executed, with the address of their current instruction
+    frames: Vec<(String, usize)>,
+    tracer: Box<dyn Tracer + 'a>,
 }
 
-impl VM {
+impl<'a> VM<'a> {
     pub fn new(functions: Functions) -> Self {
         Self {
             stack: Stack::new(),
             functions,
-            tracing: false,
+            frames: vec![],
+            tracer: Box::new(NullTracer),
         }
     }
 
+    /// Prints the executed instructions to stdout, see [`VM::set_tracer`]
     pub fn tracing(&mut self, tracing: bool) {
-        self.tracing = tracing;
+        if tracing {
+            self.set_tracer(StdoutTracer);
+        } else {
+            self.set_tracer(NullTracer);
+        }
+    }
+
+    pub fn set_tracer(&mut self, tracer: impl Tracer + 'a) {
+        self.tracer = Box::new(tracer);
     }
 
     pub fn push(&mut self, val: MetaValue) {
@@ -66,9 +122,49 @@ impl VM {
         self.stack.pop()
     }
 
-    pub fn run(&mut self, function: impl Into<String>) -> Result<(), RuntimeError> {
-        let function = self.get_function(function.into())?;
-        self.execute(function, Env::default())
+    pub fn stack(&self) -> &Stack {
+        &self.stack
+    }
+
+    /// Drops every value of the stack
+    pub fn clear(&mut self) {
+        self.stack = Stack::new();
+    }
+
+    pub fn function(&self, name: &str) -> Option<&Function> {
+        self.functions.get(name)
+    }
+
+    /// Adds functions to the ones the VM runs, replacing those of the same
+    /// name. The stack is left as it is
+    pub fn load(&mut self, functions: Functions) {
+        self.functions.extend(functions);
+    }
+
+    pub fn run(&mut self, function: impl Into<String>) -> Result<(), VmError> {
+        let name = function.into();
+        self.frames.clear();
+        self.get_function(name.clone())
+            .and_then(|function| self.execute(&name, function, Env::default()))
+            .map_err(|source| {
+                self.tracer.trace(&Event::Error(&source));
+                VmError {
+                    source,
+                    trace: self.backtrace(),
+                }
+            })
+    }
+
+    /// The frames left by a failed execution, innermost first
+    fn backtrace(&mut self) -> Vec<Frame> {
+        std::mem::take(&mut self.frames)
+            .into_iter()
+            .rev()
+            .map(|(function, addr)| Frame {
+                span: self.functions.get(&function).and_then(|f| f.span(addr)),
+                function,
+            })
+            .collect()
     }
 
     fn get_function(&self, name: String) -> Result<Function, RuntimeError> {
@@ -78,24 +174,31 @@ impl VM {
             .cloned()
     }
 
-    fn execute(&mut self, function: Function, mut env: Env) -> Result<(), RuntimeError> {
+    fn execute(
+        &mut self,
+        name: &str,
+        function: Function,
+        mut env: Env,
+    ) -> Result<(), RuntimeError> {
         let instructions = function.instructions;
         let mut pc = 0;
         env.reserve(function.locals);
+        self.frames.push((name.to_string(), 0));
+        self.tracer.trace(&Event::Call { function: name });
 
         while pc < instructions.len() {
             let addr = pc;
             pc += 1;
-
-            if self.tracing {
-                println!("-------------");
-                println!("PC: {:?}", instructions[addr]);
+            if let Some(frame) = self.frames.last_mut() {
+                frame.1 = addr;
             }
+
             match instructions[addr].clone() {
                 Inst::Nop => {}
                 Inst::PushB(v) => self.stack.push_bool(v),
                 Inst::PushI(v) => self.stack.push_int(v),
                 Inst::PushF(v) => self.stack.push_float(v),
+                Inst::PushS(v) => self.stack.push_str(v),
                 Inst::IntoInt => {
                     let mv = self.stack.pop()?;
                     let v = match mv.value {
@@ -104,7 +207,7 @@ impl VM {
                         Value::Float(v) => v.0 as i64,
                         Value::List(v) => v.len() as i64,
                         Value::Table(v) => v.len() as i64,
-                        Value::FunctionRef(_) => {
+                        Value::Str(_) | Value::FunctionRef(_) => {
                             return operation_not_defined("into_int", mv.type_name())
                         }
                     };
@@ -118,12 +221,21 @@ impl VM {
                         Value::Float(v) => v.0,
                         Value::List(v) => v.len() as f64,
                         Value::Table(v) => v.len() as f64,
-                        Value::FunctionRef(_) => {
+                        Value::Str(_) | Value::FunctionRef(_) => {
                             return operation_not_defined("into_float", mv.type_name())
                         }
                     };
                     self.stack.push_float(v);
                 }
+                Inst::IntoStr => {
+                    let v = self.stack.pop()?;
+                    self.stack.push_str(v.format());
+                }
+                Inst::Concat => {
+                    let b = self.stack.pop_str()?;
+                    let a = self.stack.pop_str()?;
+                    self.stack.push_str(a + &b);
+                }
                 Inst::And => {
                     let b = self.stack.pop_bool()?;
                     let a = self.stack.pop_bool()?;
@@ -147,6 +259,7 @@ impl VM {
                     let b = self.stack.pop()?;
                     match b.value {
                         Value::Bool(_)
+                        | Value::Str(_)
                         | Value::List(_)
                         | Value::Table(_)
                         | Value::FunctionRef(_) => {
@@ -166,6 +279,7 @@ impl VM {
                     let b = self.stack.pop()?;
                     match b.value {
                         Value::Bool(_)
+                        | Value::Str(_)
                         | Value::List(_)
                         | Value::Table(_)
                         | Value::FunctionRef(_) => {
@@ -185,6 +299,7 @@ impl VM {
                     let b = self.stack.pop()?;
                     match b.value {
                         Value::Bool(_)
+                        | Value::Str(_)
                         | Value::List(_)
                         | Value::Table(_)
                         | Value::FunctionRef(_) => {
@@ -204,6 +319,7 @@ impl VM {
                     let b = self.stack.pop()?;
                     match b.value {
                         Value::Bool(_)
+                        | Value::Str(_)
                         | Value::List(_)
                         | Value::Table(_)
                         | Value::FunctionRef(_) => {
@@ -211,7 +327,10 @@ impl VM {
                         }
                         Value::Int(b) => {
                             let a = self.stack.pop_int()?;
-                            self.stack.push_int(a / b)
+                            if b == 0 {
+                                return Err(RuntimeError::DivisionByZero);
+                            }
+                            self.stack.push_int(a.wrapping_div(b))
                         }
                         Value::Float(b) => {
                             let a = self.stack.pop_float()?;
@@ -223,6 +342,7 @@ impl VM {
                     let b = self.stack.pop()?;
                     match b.value {
                         Value::Bool(_)
+                        | Value::Str(_)
                         | Value::List(_)
                         | Value::Table(_)
                         | Value::FunctionRef(_) => {
@@ -230,7 +350,10 @@ impl VM {
                         }
                         Value::Int(b) => {
                             let a = self.stack.pop_int()?;
-                            self.stack.push_int(a % b)
+                            if b == 0 {
+                                return Err(RuntimeError::DivisionByZero);
+                            }
+                            self.stack.push_int(a.wrapping_rem(b))
                         }
                         Value::Float(b) => {
                             let a = self.stack.pop_float()?;
@@ -283,12 +406,8 @@ impl VM {
                 }
                 Inst::Call => {
                     let v = self.stack.pop_function_ref()?;
-                    let function = self.get_function(v.name)?;
-                    let env = v.env;
-                    self.execute(function, env)?;
-                    if self.tracing {
-                        println!("-------------");
-                    }
+                    let function = self.get_function(v.name.clone())?;
+                    self.execute(&v.name, function, v.env)?;
                 }
                 Inst::Bind => {
                     let env = self.stack.pop_list()?;
@@ -297,6 +416,7 @@ impl VM {
                     self.stack.push_function_ref(f);
                 }
                 Inst::PushFn(v) => self.stack.push_function_ref(v.into()),
+                Inst::Push(v) => self.stack.push(v),
                 Inst::LocalLoad(idx) => {
                     let l = env.get_local(idx)?;
                     self.stack.push(l);
@@ -400,11 +520,16 @@ impl VM {
                 }
                 Inst::Return => {}
             }
-            if self.tracing {
-                println!("Stack: {}", self.stack);
-                println!("Env: {}", env);
-            }
+            self.tracer.trace(&Event::Instruction {
+                function: name,
+                addr,
+                instruction: &instructions[addr],
+                stack: &self.stack,
+                env: &env,
+            });
         }
+        self.frames.pop();
+        self.tracer.trace(&Event::Return { function: name });
         Ok(())
     }
 }
@@ -5,7 +5,7 @@ use crate::vm::{
 use eq_float::F64;
 use std::fmt::{Display, Formatter};
 
-#[derive(Debug)]
+#[derive(Debug, Default)]
 pub struct Stack {
     stack: Vec<MetaValue>,
 }
@@ -41,6 +41,9 @@ impl Stack {
     pub fn push_float(&mut self, val: f64) {
         self.push(MetaValue::float(val))
     }
+    pub fn push_str(&mut self, val: String) {
+        self.push(MetaValue::str(val))
+    }
     pub fn push_list(&mut self, val: List) {
         self.push(MetaValue::list(val))
     }
@@ -81,15 +84,15 @@ impl Stack {
             _ => Err(RuntimeError::TypeError(v.type_name())),
         })
     }
-    // pub fn pop_string(&mut self) -> Result<f64, RuntimeError> {
-    //     self.pop().and_then(|v| match v {
-    //         MetaValue {
-    //             value: Value::Float(v),
-    //             ..
-    //         } => Ok(v),
-    //         _ => Err(RuntimeError::TypeError(v.type_name())),
-    //     })
-    // }
+    pub fn pop_str(&mut self) -> Result<String, RuntimeError> {
+        self.pop().and_then(|v| match v {
+            MetaValue {
+                value: Value::Str(v),
+                ..
+            } => Ok(v),
+            _ => Err(RuntimeError::TypeError(v.type_name())),
+        })
+    }
     pub fn pop_list(&mut self) -> Result<List, RuntimeError> {
         self.pop().and_then(|v| match v {
             MetaValue {
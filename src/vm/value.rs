@@ -2,7 +2,7 @@ use crate::vm::env::Env;
 use eq_float::F64;
 use std::{
     collections::BTreeMap,
-    fmt::{write, Display, Formatter},
+    fmt::{Display, Formatter},
     hash::Hash,
 };
 
@@ -33,6 +33,7 @@ pub enum Value {
     Int(i64),
     Float(F64),
     //Char(char),
+    Str(String),
     List(List),
     Table(Table),
     FunctionRef(FunctionRef), // Function Ref
@@ -44,6 +45,7 @@ impl Display for Value {
             Value::Bool(v) => write!(f, "{}", v),
             Value::Int(v) => write!(f, "{}", v),
             Value::Float(v) => write!(f, "{}", v),
+            Value::Str(v) => write!(f, "{:?}", v),
             Value::List(v) => {
                 write!(
                     f,
@@ -59,7 +61,7 @@ impl Display for Value {
                     f,
                     "[{}]",
                     v.iter()
-                        .map(|(k, v)| format!("{}:{}", k, v.to_string()))
+                        .map(|(k, v)| format!("{}:{}", k, v))
                         .collect::<Vec<String>>()
                         .join(",")
                 )
@@ -94,6 +96,7 @@ impl MetaValue {
             Value::Bool(_) => String::from("Bool"),
             Value::Int(_) => String::from("Int"),
             Value::Float(_) => String::from("Float"),
+            Value::Str(_) => String::from("Str"),
             Value::List(_) => String::from("List"),
             Value::Table(_) => String::from("Table"),
             Value::FunctionRef(_) => String::from("FunctionRef"),
@@ -112,6 +115,10 @@ impl MetaValue {
         Self::new(Value::Float(F64(val)))
     }
 
+    pub fn str(val: impl Into<String>) -> Self {
+        Self::new(Value::Str(val.into()))
+    }
+
     pub fn list(val: List) -> Self {
         Self::new(Value::List(val))
     }
@@ -132,6 +139,17 @@ impl MetaValue {
     pub fn is_float(&self) -> bool {
         matches!(self.value, Value::Float(_))
     }
+    pub fn is_str(&self) -> bool {
+        matches!(self.value, Value::Str(_))
+    }
+
+    /// Text of the value when formatted into a string, strings aren't quoted
+    pub fn format(&self) -> String {
+        match &self.value {
+            Value::Str(v) => v.clone(),
+            _ => self.to_string(),
+        }
+    }
 }
 
 impl From<i64> for MetaValue {
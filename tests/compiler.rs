@@ -0,0 +1,403 @@
+use mana::{
+    compiler::{compile, CompileError},
+    lang::{diagnostic::Diagnostic, parser::Parser, span::Span},
+    vm::{function::Functions, instructions::Inst, value::MetaValue, RuntimeError, VM},
+};
+
+fn build(source: &str) -> Result<Functions, CompileError> {
+    let ast = Parser::new(source).parse().unwrap();
+    compile(&ast)
+}
+
+fn run(source: &str) -> MetaValue {
+    let mut vm = VM::new(build(source).unwrap());
+    vm.run("main").unwrap();
+    vm.pop().unwrap()
+}
+
+#[test]
+fn test_builtins() {
+    assert_eq!(run("def main = 2 3 swap - dup *"), MetaValue::int(1));
+    assert_eq!(run("def main = 1.5 2.0 *"), MetaValue::float(3.0));
+    assert_eq!(run("def main = 2 3 < true and"), MetaValue::bool(true));
+}
+
+#[test]
+fn test_definitions() {
+    let source = r#"
+def square = dup *
+def main =
+    7 square
+    1 +
+"#;
+
+    assert_eq!(run(source), MetaValue::int(50));
+}
+
+#[test]
+fn test_duplicate_definition() {
+    let source = r#"
+def main = 1
+def main = 2
+"#;
+
+    assert_eq!(
+        build(source).err(),
+        Some(CompileError::DuplicateDefinition("main".into()))
+    );
+}
+
+#[test]
+fn test_builtin_redefinition() {
+    for name in ["dup", "+"] {
+        let source = format!("def {} = 1\ndef main = 1 {}", name, name);
+        assert!(
+            matches!(
+                build(&source).err(),
+                Some(CompileError::BuiltinRedefinition { name: n, .. }) if n == name
+            ),
+            "{}",
+            name
+        );
+    }
+}
+
+#[test]
+fn test_closures() {
+    assert_eq!(run("def main = 2 { 1 + } call"), MetaValue::int(3));
+    assert_eq!(run("def main = 2 { } call"), MetaValue::int(2));
+
+    let source = r#"
+def apply = call
+def main =
+    3 { { 2 * } call } apply
+    1 +
+"#;
+
+    assert_eq!(run(source), MetaValue::int(7));
+}
+
+#[test]
+fn test_quoted_terms() {
+    let source = r#"
+def apply = call
+def square = dup *
+def main =
+    6 7 \* apply
+    \square apply
+"#;
+
+    assert_eq!(run(source), MetaValue::int(1764));
+
+    // Quoted builtins run through wrappers no definition can replace
+    let functions = build("def main = 2 \\dup call").unwrap();
+    assert!(functions.contains_key("#dup"));
+    assert!(!functions.contains_key("dup"));
+}
+
+#[test]
+fn test_strings() {
+    assert_eq!(
+        run(r#"def main = "con" "cat" concat"#),
+        MetaValue::str("concat")
+    );
+
+    let source = r#"
+def name = "world"
+def main = "hello {name}, {1 2 +} is {3 str "!" concat}"
+"#;
+
+    assert_eq!(run(source), MetaValue::str("hello world, 3 is 3!"));
+
+    // Strings have no numeric value
+    for source in [r#"def main = "42" int"#, r#"def main = "42" float"#] {
+        let error = VM::new(build(source).unwrap()).run("main").unwrap_err();
+        assert!(
+            matches!(error.source, RuntimeError::OperationNotDefined(_, ref t) if t == "Str"),
+            "{}",
+            source
+        );
+    }
+}
+
+#[test]
+fn test_interpolation_effects() {
+    for (source, effect, span) in [
+        (r#"def main = 1 2 "a{+}b""#, "( 2 -- 1 )", Span::new(18, 1)),
+        (r#"def main = 7 "x{1 2}y""#, "( 0 -- 2 )", Span::new(16, 3)),
+        (r#"def main = "{1 -> a}""#, "( 0 -- 0 )", Span::new(13, 6)),
+    ] {
+        match build(source) {
+            Err(CompileError::ValueEffect {
+                literal: "Interpolated code",
+                effect: e,
+                span: s,
+            }) => {
+                assert_eq!((e.to_string().as_str(), s), (effect, span), "{}", source)
+            }
+            other => panic!("expected an interpolation error, got {:?}", other),
+        }
+    }
+
+    // Locals push a value, and code of unknown effect is left to run time
+    assert_eq!(
+        run(r#"def main = 2 -> n "{n}{{ 1 } call}""#),
+        MetaValue::str("21")
+    );
+}
+
+#[test]
+fn test_bindings() {
+    assert_eq!(
+        run("def main = 7 2\n    -> a b\n    a b -"),
+        MetaValue::int(5)
+    );
+
+    let source = r#"
+def main =
+    1 2
+    -> n total
+    n total +
+    -> n
+    n n *
+"#;
+
+    assert_eq!(run(source), MetaValue::int(9));
+
+    let source = r#"
+def adder =
+    -> n
+    { -> x
+      x n + }
+def main = 2 10 adder call
+"#;
+
+    assert_eq!(run(source), MetaValue::int(12));
+}
+
+#[test]
+fn test_unknown_words() {
+    let source = r#"
+def main =
+    -> n
+    n m +
+"#;
+
+    let error = build(source).unwrap_err();
+    assert!(matches!(
+        error,
+        CompileError::UnknownWord { ref word, suggestion: None, .. } if word == "m"
+    ));
+    assert_eq!(error.span().map(|s| (s.start, s.length)), Some((27, 1)));
+
+    assert!(matches!(
+        build(r"def main = \missing"),
+        Err(CompileError::UnknownWord { .. })
+    ));
+
+    // Misspelled words are reported with the closest visible name, even in
+    // code that never runs
+    let source = "def square = dup *\ndef main = 3 sqare false { quare } { } if\n";
+    let error = build(source).unwrap_err();
+    assert_eq!(
+        error,
+        CompileError::UnknownWord {
+            word: "sqare".into(),
+            suggestion: Some("square".into()),
+            span: Span::new(32, 5),
+        }
+    );
+    assert_eq!(
+        Diagnostic::from(&error).notes,
+        vec!["did you mean 'square'?".to_string()]
+    );
+    assert!(matches!(
+        build("def main = 2 -> count\n    coun 1 +"),
+        Err(CompileError::UnknownWord { suggestion: Some(ref s), .. }) if s == "count"
+    ));
+    assert!(matches!(
+        build("def main = 1 2 frobnicate"),
+        Err(CompileError::UnknownWord {
+            suggestion: None,
+            ..
+        })
+    ));
+}
+
+#[test]
+fn test_stack_effects() {
+    let source = r#"
+def square ( n -- n ) = dup *
+def sum3 ( a b c -- sum ) = + +
+def squares = dup square square
+def main ( -- n ) =
+    2 squares 1 2 sum3
+    -> a b
+    a b +
+"#;
+
+    assert_eq!(run(source), MetaValue::int(21));
+
+    // Calls of unknown effect end the check
+    assert!(build("def main ( -- ) = { 1 2 } call").is_ok());
+}
+
+#[test]
+fn test_stack_effect_errors() {
+    let source = "def square = dup *\ndef f ( a -- b ) = square +\n";
+    let error = build(source).unwrap_err();
+    assert_eq!(
+        error.to_string(),
+        "'+' takes 2 values but only 1 are on the stack"
+    );
+    assert_eq!(error.span().map(|s| s.start), Some(source.len() - 2));
+
+    let source = "def f ( a -- b ) = dup \"{1}\"";
+    let error = build(source).unwrap_err();
+    assert_eq!(
+        error.to_string(),
+        "'f' leaves 3 values but its stack effect declares 1"
+    );
+    assert_eq!(error.span().map(|s| s.start), Some(23));
+}
+
+#[test]
+fn test_collection_literals() {
+    let list = |items: Vec<MetaValue>| MetaValue::list(items);
+    assert_eq!(
+        run("def main = [1 [2.5 \"x\"] []]"),
+        list(vec![
+            MetaValue::int(1),
+            list(vec![MetaValue::float(2.5), MetaValue::str("x")]),
+            list(vec![]),
+        ])
+    );
+    assert_eq!(
+        run("def main = 2 -> n\n    [n 3]"),
+        list(vec![MetaValue::int(2), MetaValue::int(3)])
+    );
+
+    let table = run("def main = 40 -> n\n    { name: \"x\", age: n 2 + } \"age\" Table.get");
+    assert_eq!(table, MetaValue::int(42));
+
+    // Constant literals are built at compile time
+    let functions = build("def main = { name: \"x\", ids: [1 2] }").unwrap();
+    let main = &functions["main"];
+    assert!(matches!(main.instructions.as_slice(), [Inst::Push(_)]));
+
+    // Each list item and table entry leaves one value
+    for (source, literal, span) in [
+        ("def main = [1 2 +]", "List items", Span::new(16, 1)),
+        ("def main = [1 dup]", "List items", Span::new(14, 3)),
+        ("def main = { a: 1 2 }", "Table entries", Span::new(16, 3)),
+    ] {
+        match build(source) {
+            Err(CompileError::ValueEffect {
+                literal: l,
+                span: s,
+                ..
+            }) => assert_eq!((l, s), (literal, span), "{}", source),
+            other => panic!("expected a value error, got {:?}", other),
+        }
+    }
+}
+
+#[test]
+fn test_control_flow() {
+    let source = r#"
+def abs = dup 0 < { 0 swap - } { } if
+def factorial ( n -- n ) =
+    -> n
+    1 1 n {
+        -> total i
+        total i * i 1 +
+    } times drop
+def triple = -> n
+    0 n { 3 + } times
+def countdown = { dup 0 > } { 1 - } while
+def main = -3 abs 4 factorial + 2 triple + 10 countdown +
+"#;
+    assert_eq!(run(source), MetaValue::int(33));
+
+    // Literal quotations are inlined into branches
+    let functions = build(source).unwrap();
+    let abs = &functions["abs"].instructions;
+    assert!(abs.iter().any(|i| matches!(i, Inst::BranchIfNot(_))));
+    assert!(!abs.iter().any(|i| matches!(i, Inst::Call)));
+
+    // Quotations only known at runtime are called
+    let source = r#"
+def choose = if
+def main =
+    { 2 + } -> add
+    false { 1 } { 2 } choose 3 add times
+"#;
+    assert_eq!(run(source), MetaValue::int(8));
+
+    // Branches leaving as many values give the control word an effect
+    assert!(build("def f ( a -- b ) = { 1 } { 2 } if").is_ok());
+    let error = build("def f ( a -- b ) = { 1 } { 2 } if 3").unwrap_err();
+    assert!(matches!(
+        error,
+        CompileError::EffectMismatch { leaves: 2, .. }
+    ));
+
+    // Branches changing the stack differently are an error on the `if`
+    for source in [
+        "def f ( a -- b ) = { 1 } { 1 2 } if 3",
+        "def f = { 1 } { drop } if",
+    ] {
+        let error = build(source).unwrap_err();
+        assert!(
+            matches!(error, CompileError::UnbalancedBranches { .. }),
+            "{}",
+            source
+        );
+        assert_eq!(error.span(), Some(Span::new(source.find("if").unwrap(), 2)));
+    }
+}
+
+#[test]
+fn test_constant_folding() {
+    let instructions = |source: &str| build(source).unwrap()["main"].instructions.clone();
+
+    assert!(matches!(
+        instructions("def main = 2 3 + 4 * dup 80 <= not not").as_slice(),
+        [Inst::PushI(20), Inst::PushB(true)]
+    ));
+    assert!(matches!(
+        instructions("def main = \"{1 2 +} apples\"").as_slice(),
+        [Inst::PushS(s)] if s == "3 apples"
+    ));
+
+    // Branches that can't run are removed, errors in them can't happen
+    assert!(matches!(
+        instructions("def main = 1 2 < { \"yes\" } { 1 0 / } if").as_slice(),
+        [Inst::PushS(s)] if s == "yes"
+    ));
+    assert!(matches!(
+        instructions("def main = 0 { 1 } times { false } { 2 } while 5").as_slice(),
+        [Inst::PushI(5)]
+    ));
+    assert_eq!(run("def main = 2 -> n\n    n 3 4 * +"), MetaValue::int(14));
+
+    let source = "def main = -> n\n    n 0 /";
+    let error = build(source).unwrap_err();
+    assert_eq!(
+        error,
+        CompileError::DivisionByZero(Span::new(source.len() - 1, 1))
+    );
+
+    // Only divisions certain to run are errors
+    assert!(build("def f = { 1 0 / } { 2 } if").is_ok());
+    assert!(build("def main = { 1 0 / } drop 5").is_ok());
+    assert!(build("def f = { 1 0 % } times").is_ok());
+    assert!(matches!(
+        build("def main = true { 1 0 / } { 2 } if"),
+        Err(CompileError::DivisionByZero(_))
+    ));
+    assert!(matches!(
+        build("def main = 2 { 1 0 / drop } times"),
+        Err(CompileError::DivisionByZero(_))
+    ));
+}
@@ -0,0 +1,61 @@
+use mana::lang::{
+    diagnostic::Diagnostic,
+    parser::Parser,
+    source::{Location, SourceFile, SourceMap},
+    span::Span,
+};
+
+#[test]
+fn test_locations() {
+    let file = SourceFile::new("main.mana", "def a = 1\n\ndef b =\n    2 +\n");
+
+    assert_eq!(file.line_count(), 5);
+    assert_eq!(file.location(0), Location { line: 1, column: 1 });
+    assert_eq!(file.location(10), Location { line: 2, column: 1 });
+    assert_eq!(file.location(23), Location { line: 4, column: 5 });
+    assert_eq!(file.line(4), Some("    2 +"));
+    assert_eq!(file.line(5), Some(""));
+    assert_eq!(file.line(6), None);
+    assert_eq!(SourceFile::new("a", "x\r\ny").line(1), Some("x"));
+}
+
+#[test]
+fn test_render_parse_error() {
+    let mut sources = SourceMap::new();
+    sources.add("other.mana", "");
+    let file = sources.add("main.mana", "def a = 1\ndef b =\n    2 @ +\n");
+    let source = &sources.file(file).unwrap().source;
+    let error = Parser::new(source).with_file(file).parse().unwrap_err();
+
+    assert_eq!(
+        Diagnostic::from(&error).render(&sources),
+        "\
+error: Unexpected char '@'
+ --> main.mana:3:7
+  |
+3 |     2 @ +
+  |       ^
+"
+    );
+}
+
+#[test]
+fn test_render_with_notes() {
+    let mut sources = SourceMap::new();
+    sources.add("main.mana", "def main = foo bar\n");
+    let diagnostic = Diagnostic::warning("Unused word")
+        .with_span(Some(Span::new(11, 3)))
+        .with_note("remove it");
+
+    assert_eq!(
+        diagnostic.render(&sources),
+        "\
+warning: Unused word
+ --> main.mana:1:12
+  |
+1 | def main = foo bar
+  |            ^^^
+  = note: remove it
+"
+    );
+}
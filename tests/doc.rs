@@ -0,0 +1,64 @@
+use mana::lang::{
+    doc,
+    loader::{Module, Program},
+    parser::Parser,
+    source::FileId,
+};
+use std::path::PathBuf;
+
+fn program(modules: &[(&str, &str)]) -> Program {
+    Program {
+        modules: modules
+            .iter()
+            .map(|(name, source)| Module {
+                name: name.to_string(),
+                path: match *name {
+                    "" => PathBuf::from("main.mana"),
+                    name => PathBuf::from(format!("lib/{}.mana", name)),
+                },
+                file: FileId::default(),
+                ast: Parser::new(source).parse().unwrap(),
+            })
+            .collect(),
+    }
+}
+
+#[test]
+fn test_markdown() {
+    let program = program(&[
+        (
+            "Math",
+            "## Squares a number\ndef square ( n -- n ) = dup *\n",
+        ),
+        (
+            "",
+            "import Math\n\n## Entry point\ndef main ( -- ) = 3 Math.square drop\n",
+        ),
+    ]);
+    let markdown = doc::markdown(&program);
+
+    // The root module comes first, named after its file
+    assert!(markdown.find("# main").unwrap() < markdown.find("# Math").unwrap());
+    assert!(markdown.contains("Imports: [Math](#Math)"));
+    assert!(markdown.contains("- [`square`](#Math.square)"));
+    assert!(markdown
+        .contains("<a id=\"Math.square\"></a>\n\n## `square ( n -- n )`\n\nSquares a number\n"));
+    assert!(markdown.contains("## `main ( -- )`\n\nEntry point\n"));
+}
+
+#[test]
+fn test_html() {
+    let program = program(&[(
+        "Cmp",
+        "## True if a < b\n##\n## Ints only\ndef less ( a b -- bool ) = <\ndef plain = 1\n",
+    )]);
+    let html = doc::html(&program);
+
+    assert!(html.starts_with("<!DOCTYPE html>"));
+    assert!(html.contains("<li><a href=\"#Cmp.less\">less</a></li>"));
+    assert!(html.contains(
+        "<article id=\"Cmp.less\">\n<h2><code>less ( a b -- bool )</code></h2>\n\
+         <p>True if a &lt; b</p>\n<p>Ints only</p>\n</article>"
+    ));
+    assert!(html.contains("<h2><code>plain</code></h2>\n</article>"));
+}
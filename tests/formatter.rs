@@ -0,0 +1,61 @@
+use mana::lang::formatter::format;
+
+fn assert_formatted(source: &str, expected: &str) {
+    let formatted = format(source).unwrap();
+    assert_eq!(formatted, expected);
+    assert_eq!(format(&formatted).unwrap(), expected);
+}
+
+#[test]
+fn test_indentation() {
+    assert_formatted(
+        "def fact =\n  dup 1 >\n      { dup 1 - fact * }\n  swap\n",
+        "def fact =\n    dup 1 >\n        { dup 1 - fact * }\n    swap\n",
+    );
+    assert_formatted("def   a   =  1    2   +", "def a = 1 2 +\n");
+    assert_formatted("def a = \\+   {1 2}", "def a = \\+ { 1 2 }\n");
+}
+
+#[test]
+fn test_comments_and_blank_lines() {
+    assert_formatted(
+        "\n\n# header\n\n\n\nimport Math    # math\ndef a =\n      # inner\n      1\n\n\n",
+        "# header\n\nimport Math # math\ndef a =\n    # inner\n    1\n",
+    );
+}
+
+#[test]
+fn test_aligned_definitions() {
+    assert_formatted(
+        "def inc = 1 +\ndef double = 2 *\n\ndef sq = dup *\ndef block =\n    1\n",
+        "def inc    = 1 +\ndef double = 2 *\n\ndef sq = dup *\ndef block =\n    1\n",
+    );
+}
+
+#[test]
+fn test_wrapped_closures() {
+    let source =
+        "def long = items { element transform validate store } map reduce-everything-else done\n";
+    assert_formatted(
+        source,
+        "def long = items {\n    element transform validate store\n} map reduce-everything-else done\n",
+    );
+    let source = "def f =\n    { a { bbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbb cccccccccccccccccccccccccccccccc } dddddddddd }\n";
+    assert_formatted(
+        source,
+        "def f =\n    {\n        a {\n            bbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbb cccccccccccccccccccccccccccccccc\n        } dddddddddd\n    }\n",
+    );
+}
+
+#[test]
+fn test_collection_literals() {
+    assert_formatted(
+        "def a = [ 1 2  [3] ]\ndef b = { name: \"x\" , age: 3 }\n",
+        "def a = [1 2 [3]]\ndef b = { name: \"x\", age: 3 }\n",
+    );
+}
+
+#[test]
+fn test_syntax_errors() {
+    assert!(format("def = 1").is_err());
+}
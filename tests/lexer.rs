@@ -0,0 +1,259 @@
+use mana::lang::{
+    lexer::Lexer,
+    parser::{ParseError, ParseErrorKind},
+    source::SourceFile,
+    span::Span,
+    token::{StringPart, Token, TokenKind, TokenValue},
+};
+use std::borrow::Cow;
+
+fn tokens(source: &str) -> Result<Vec<Token<'_>>, ParseError> {
+    Lexer::new(source)
+        .filter(|token| !matches!(token, Ok(token) if token.kind == TokenKind::Eof))
+        .collect()
+}
+
+fn values(source: &str) -> Vec<TokenValue<'_>> {
+    tokens(source)
+        .unwrap()
+        .into_iter()
+        .filter_map(|t| t.value)
+        .collect()
+}
+
+#[test]
+fn test_escapes() {
+    assert_eq!(
+        values(r#""a\tb\n" "say \"hi\"" "back\\slash" "\u{48}\u{1F600}\0""#),
+        vec![
+            TokenValue::String("a\tb\n".into()),
+            TokenValue::String("say \"hi\"".into()),
+            TokenValue::String("back\\slash".into()),
+            TokenValue::String("H\u{1F600}\0".into()),
+        ]
+    );
+    assert_eq!(
+        values(r#"'\'' '\n' '\\' '"' '\u{e9}'"#),
+        vec![
+            TokenValue::Char('\''),
+            TokenValue::Char('\n'),
+            TokenValue::Char('\\'),
+            TokenValue::Char('"'),
+            TokenValue::Char('é'),
+        ]
+    );
+}
+
+#[test]
+fn test_invalid_escapes() {
+    for (source, escape, span) in [
+        (r#""ab\qc""#, r"\q", Span::new(3, 2)),
+        (r#""\u{110000}""#, r"\u{110000}", Span::new(1, 10)),
+        (r#""\u12""#, r"\u12", Span::new(1, 4)),
+        (r"'\x'", r"\x", Span::new(1, 2)),
+    ] {
+        let error = tokens(source).unwrap_err();
+        assert_eq!(error.span(), Some(span), "{}", source);
+        assert!(
+            matches!(error.kind(), ParseErrorKind::InvalidEscape(e) if e == escape),
+            "{}",
+            source
+        );
+    }
+}
+
+#[test]
+fn test_numbers() {
+    assert_eq!(
+        values("0xFF 0o17 0b1010 1_000_000 -42 -0x10 1.5e-3 2E3 1e+2 -0.5"),
+        vec![
+            TokenValue::Int(255),
+            TokenValue::Int(15),
+            TokenValue::Int(10),
+            TokenValue::Int(1_000_000),
+            TokenValue::Int(-42),
+            TokenValue::Int(-16),
+            TokenValue::Float(1.5e-3),
+            TokenValue::Float(2000.0),
+            TokenValue::Float(100.0),
+            TokenValue::Float(-0.5),
+        ]
+    );
+    assert_eq!(
+        values("-9223372036854775808"),
+        vec![TokenValue::Int(i64::MIN)]
+    );
+
+    // A lone minus, or one followed by a space, is a term
+    let kinds: Vec<TokenKind> = tokens("5 - 1 -x")
+        .unwrap()
+        .into_iter()
+        .map(|t| t.kind)
+        .collect();
+    assert_eq!(
+        kinds,
+        vec![
+            TokenKind::Int,
+            TokenKind::Term,
+            TokenKind::Int,
+            TokenKind::Term
+        ]
+    );
+}
+
+#[test]
+fn test_invalid_numbers() {
+    for (source, span) in [
+        ("1 9223372036854775808", Span::new(2, 19)),
+        ("0xFFFF_FFFF_FFFF_FFFF", Span::new(0, 21)),
+        ("0b102 1", Span::new(0, 5)),
+        ("1 0xFG", Span::new(2, 4)),
+        ("0o78", Span::new(0, 4)),
+    ] {
+        let error = tokens(source).unwrap_err();
+        assert_eq!(error.span(), Some(span), "{}", source);
+        assert!(matches!(error.kind(), ParseErrorKind::ParseInt(_)));
+    }
+
+    let error = tokens("1e999").unwrap_err();
+    assert_eq!(error.span(), Some(Span::new(0, 5)));
+    assert!(matches!(error.kind(), ParseErrorKind::ParseFloat(n) if n == "1e999"));
+
+    for source in ["1__0", "1_", "0x", "1.", "1e"] {
+        let error = tokens(source).unwrap_err();
+        assert!(
+            matches!(error.kind(), ParseErrorKind::ExpectedDigit),
+            "{}",
+            source
+        );
+    }
+}
+
+#[test]
+fn test_string_forms() {
+    let source = r#"
+def query =
+    """
+    SELECT *
+      FROM users
+    """
+    r"C:\path\{raw}" r"""a\n"""
+    """one line"""
+"#;
+
+    assert_eq!(
+        values(source),
+        vec![
+            TokenValue::String("query".into()),
+            TokenValue::String("SELECT *\n  FROM users".into()),
+            TokenValue::String(r"C:\path\{raw}".into()),
+            TokenValue::String(r"a\n".into()),
+            TokenValue::String("one line".into()),
+        ]
+    );
+
+    // Lines inside a literal don't affect indentation
+    let kinds: Vec<TokenKind> = tokens("def a =\n    \"\"\"\nx\n  \"\"\" 1\ndef b = 2")
+        .unwrap()
+        .into_iter()
+        .map(|t| t.kind)
+        .collect();
+    assert_eq!(
+        kinds,
+        vec![
+            TokenKind::Def,
+            TokenKind::Term,
+            TokenKind::Eq,
+            TokenKind::Indent,
+            TokenKind::String,
+            TokenKind::Int,
+            TokenKind::Dedent,
+            TokenKind::Def,
+            TokenKind::Term,
+            TokenKind::Eq,
+            TokenKind::Int,
+        ]
+    );
+}
+
+#[test]
+fn test_interpolated_strings() {
+    assert_eq!(
+        values(r#""hello {name}, \{x\}: { 1 { 2 } }!""#),
+        vec![TokenValue::Parts(vec![
+            StringPart::Literal("hello ".into()),
+            StringPart::Code {
+                source: "name",
+                offset: 8,
+            },
+            StringPart::Literal(", {x}: ".into()),
+            StringPart::Code {
+                source: " 1 { 2 } ",
+                offset: 23,
+            },
+            StringPart::Literal("!".into()),
+        ])]
+    );
+
+    let error = tokens(r#""a {b""#).unwrap_err();
+    assert_eq!(error.span(), Some(Span::new(3, 3)));
+    assert!(matches!(
+        error.kind(),
+        ParseErrorKind::UnterminatedInterpolation
+    ));
+}
+
+#[test]
+fn test_byte_offsets() {
+    let source = "\"é\" cafe # ☕\nx";
+    let tokens = tokens(source).unwrap();
+    let spans: Vec<Span> = tokens.iter().map(|t| t.span).collect();
+    assert_eq!(
+        spans,
+        vec![Span::new(0, 4), Span::new(5, 4), Span::new(16, 1)]
+    );
+    assert_eq!(&source[spans[0].start..spans[0].end()], "\"é\"");
+
+    // Plain text is borrowed from the source rather than copied
+    assert!(matches!(
+        &tokens[1].value,
+        Some(TokenValue::String(Cow::Borrowed("cafe")))
+    ));
+
+    let file = SourceFile::new("main.mana", source);
+    let location = file.location(spans[1].end());
+    assert_eq!((location.line, location.column), (1, 9));
+    assert_eq!(file.offset(location), Some(9));
+    let location = file.location(spans[2].start);
+    assert_eq!((location.line, location.column), (2, 1));
+}
+
+#[test]
+fn test_doc_comments() {
+    let source = "## Squares\n##   a number\ndef square = dup * ## trailing\n# plain\n";
+    let tokens = tokens(source).unwrap();
+    let kinds: Vec<(TokenKind, Option<&str>)> =
+        tokens.iter().map(|t| (t.kind, t.value_str())).collect();
+    assert_eq!(
+        kinds[..3],
+        vec![
+            (TokenKind::DocComment, Some("Squares")),
+            (TokenKind::DocComment, Some("  a number")),
+            (TokenKind::Def, None),
+        ]
+    );
+
+    // Trailing and indented ones are plain comments
+    let docs = |source| {
+        Lexer::new(source)
+            .flatten()
+            .filter(|t| t.kind == TokenKind::DocComment)
+            .count()
+    };
+    assert_eq!(docs(source), 2);
+    assert_eq!(docs("def a =\n    1\n    ## about a\ndef b = 2\n"), 0);
+
+    // Lossless lexers keep them as comments
+    let lossless: Vec<TokenKind> = Lexer::lossless(source).map(|t| t.unwrap().kind).collect();
+    assert!(!lossless.contains(&TokenKind::DocComment));
+}
@@ -0,0 +1,216 @@
+use mana::{
+    compiler::{compile_program, CompileError},
+    lang::{
+        loader::{LoadError, Loader},
+        source::SourceMap,
+    },
+    vm::{value::MetaValue, VM},
+};
+use std::{fs, path::PathBuf};
+
+/// Writes the given files into a fresh directory, returns its path
+fn project(name: &str, files: &[(&str, &str)]) -> PathBuf {
+    let dir = PathBuf::from(env!("CARGO_TARGET_TMPDIR")).join(name);
+    let _ = fs::remove_dir_all(&dir);
+    for (path, source) in files {
+        let path = dir.join(path);
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(path, source).unwrap();
+    }
+    dir
+}
+
+#[test]
+fn test_imports() {
+    let dir = project(
+        "imports",
+        &[
+            (
+                "main.mana",
+                "import Math\nimport Data.Pair\n\ndef main = 3 Math.square Data.Pair.first\n",
+            ),
+            ("Math.mana", "def square = dup *\n"),
+            (
+                "Data/Pair.mana",
+                "import Math\n\ndef first = pair drop\ndef pair = dup Math.square\n",
+            ),
+        ],
+    );
+
+    let program = Loader::new()
+        .load(&mut SourceMap::new(), dir.join("main.mana"))
+        .unwrap();
+    let names: Vec<&str> = program.modules.iter().map(|m| m.name.as_str()).collect();
+    assert_eq!(names, vec!["Math", "Data.Pair", ""]);
+
+    let mut vm = VM::new(compile_program(&program).unwrap());
+    vm.run("main").unwrap();
+    assert_eq!(vm.pop(), Ok(MetaValue::int(9)));
+}
+
+#[test]
+fn test_search_path() {
+    let dir = project(
+        "search_path",
+        &[
+            ("app/main.mana", "import Math\ndef main = 4 Math.square\n"),
+            ("lib/Math.mana", "def square = dup *\n"),
+        ],
+    );
+
+    let program = Loader::new()
+        .with_search_path(dir.join("lib"))
+        .load(&mut SourceMap::new(), dir.join("app/main.mana"))
+        .unwrap();
+
+    let mut vm = VM::new(compile_program(&program).unwrap());
+    vm.run("main").unwrap();
+    assert_eq!(vm.pop(), Ok(MetaValue::int(16)));
+}
+
+#[test]
+fn test_missing_module() {
+    let dir = project("missing_module", &[("main.mana", "import Nope\n")]);
+
+    match Loader::new().load(&mut SourceMap::new(), dir.join("main.mana")) {
+        Err(LoadError::ModuleNotFound { module, .. }) => assert_eq!(module, "Nope"),
+        other => panic!("expected a missing module, got {:?}", other),
+    }
+}
+
+#[test]
+fn test_import_cycle() {
+    let dir = project(
+        "import_cycle",
+        &[
+            ("main.mana", "import A\n"),
+            ("A.mana", "import B\n"),
+            ("B.mana", "import A\n"),
+        ],
+    );
+
+    match Loader::new().load(&mut SourceMap::new(), dir.join("main.mana")) {
+        Err(LoadError::ImportCycle(cycle)) => assert_eq!(cycle, vec!["A", "B", "A"]),
+        other => panic!("expected an import cycle, got {:?}", other),
+    }
+}
+
+#[test]
+fn test_error_locations() {
+    let dir = project(
+        "error_locations",
+        &[
+            ("main.mana", "import Math\ndef main = Math.half\n"),
+            ("Math.mana", "def half = 0 zero /\ndef zero = 0 @\n"),
+        ],
+    );
+
+    let mut sources = SourceMap::new();
+    let error = Loader::new()
+        .load(&mut sources, dir.join("main.mana"))
+        .unwrap_err();
+    assert!(error
+        .render(&sources)
+        .contains(&format!("--> {}:2:14", dir.join("Math.mana").display())));
+
+    fs::write(dir.join("Math.mana"), "def half = 2 /\n").unwrap();
+    let mut sources = SourceMap::new();
+    let program = Loader::new()
+        .load(&mut sources, dir.join("main.mana"))
+        .unwrap();
+    let error = VM::new(compile_program(&program).unwrap())
+        .run("main")
+        .unwrap_err();
+
+    let math = dir.join("Math.mana").display().to_string();
+    let main = dir.join("main.mana").display().to_string();
+    assert_eq!(
+        error.render(&sources),
+        format!(
+            "\
+error: the stack is empty
+ --> {math}:1:14
+  |
+1 | def half = 2 /
+  |              ^
+  = note: in Math.half at {math}:1:14
+  = note: in main at {main}:2:12
+"
+        )
+    );
+}
+
+#[test]
+fn test_unimported_modules() {
+    let dir = project(
+        "unimported_modules",
+        &[
+            (
+                "main.mana",
+                "import Math\nimport Stats\ndef main = 2 Stats.mean\n",
+            ),
+            ("Math.mana", "def half = 2 /\n"),
+            ("Stats.mana", "def mean = + Math.half\n"),
+        ],
+    );
+
+    // Stats doesn't import Math, so its definitions aren't visible there
+    let program = Loader::new()
+        .load(&mut SourceMap::new(), dir.join("main.mana"))
+        .unwrap();
+    assert!(matches!(
+        compile_program(&program),
+        Err(CompileError::UnknownWord { ref word, .. }) if word == "Math.half"
+    ));
+}
+
+#[test]
+fn test_module_conflict() {
+    let dir = project(
+        "module_conflict",
+        &[
+            (
+                "main.mana",
+                "import lib.Util\nimport Helper\ndef main = Helper.v\n",
+            ),
+            ("Helper.mana", "def v = 1\n"),
+            ("lib/Util.mana", "import Helper\ndef u = Helper.v\n"),
+            ("lib/Helper.mana", "def v = 2\n"),
+        ],
+    );
+
+    match Loader::new().load(&mut SourceMap::new(), dir.join("main.mana")) {
+        Err(LoadError::ModuleConflict {
+            module,
+            first,
+            second,
+        }) => {
+            assert_eq!(module, "Helper");
+            assert_eq!(first, dir.join("lib/Helper.mana"));
+            assert_eq!(second, dir.join("Helper.mana"));
+        }
+        other => panic!("expected a module conflict, got {:?}", other),
+    }
+}
+
+#[test]
+fn test_control_words_at_runtime() {
+    let dir = project(
+        "control_words_at_runtime",
+        &[
+            (
+                "main.mana",
+                "import Lib\ndef if = drop drop drop 99\ndef main = true { 1 } { 2 } Lib.choose\n",
+            ),
+            ("Lib.mana", "def choose = if\n"),
+        ],
+    );
+
+    // The root module's `if` doesn't replace the function running `if`
+    let program = Loader::new()
+        .load(&mut SourceMap::new(), dir.join("main.mana"))
+        .unwrap();
+    let mut vm = VM::new(compile_program(&program).unwrap());
+    vm.run("main").unwrap();
+    assert_eq!(vm.pop(), Ok(MetaValue::int(1)));
+}
@@ -0,0 +1,214 @@
+use mana::lsp::transport::{read_message, write_message};
+use serde_json::{json, Value};
+use std::{
+    io::BufReader,
+    process::{Child, ChildStdin, ChildStdout, Command, Stdio},
+};
+
+const URI: &str = "file:///main.mana";
+const SOURCE: &str = "## Squares the top of the stack
+def square = dup *
+
+def main =
+    3 square
+    square
+";
+
+/// Drives a `mana-lsp` process over its stdin and stdout
+struct Client {
+    process: Child,
+    stdin: ChildStdin,
+    stdout: BufReader<ChildStdout>,
+    id: i64,
+}
+
+impl Client {
+    fn start() -> Self {
+        let mut process = Command::new(env!("CARGO_BIN_EXE_mana-lsp"))
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .spawn()
+            .unwrap();
+        let stdin = process.stdin.take().unwrap();
+        let stdout = BufReader::new(process.stdout.take().unwrap());
+        Self {
+            process,
+            stdin,
+            stdout,
+            id: 0,
+        }
+    }
+
+    fn receive(&mut self) -> Value {
+        read_message(&mut self.stdout).unwrap().unwrap()
+    }
+
+    fn notify(&mut self, method: &str, params: Value) {
+        let message = json!({ "jsonrpc": "2.0", "method": method, "params": params });
+        write_message(&mut self.stdin, &message).unwrap();
+    }
+
+    fn request(&mut self, method: &str, params: Value) -> Value {
+        self.id += 1;
+        let message =
+            json!({ "jsonrpc": "2.0", "id": self.id, "method": method, "params": params });
+        write_message(&mut self.stdin, &message).unwrap();
+
+        let response = self.receive();
+        assert_eq!(response["id"], self.id);
+        response
+    }
+
+    fn at(&mut self, method: &str, line: u32, character: u32) -> Value {
+        self.request(
+            method,
+            json!({
+                "textDocument": { "uri": URI },
+                "position": { "line": line, "character": character },
+                "context": { "includeDeclaration": true },
+            }),
+        )["result"]
+            .clone()
+    }
+
+    fn open(&mut self, text: &str) -> Value {
+        self.notify(
+            "textDocument/didOpen",
+            json!({ "textDocument": { "uri": URI, "languageId": "mana", "version": 1, "text": text } }),
+        );
+        self.receive()
+    }
+
+    fn stop(mut self) -> bool {
+        self.request("shutdown", Value::Null);
+        self.notify("exit", Value::Null);
+        self.process.wait().unwrap().success()
+    }
+}
+
+fn range(line: u32, start: u32, end: u32) -> Value {
+    json!({
+        "start": { "line": line, "character": start },
+        "end": { "line": line, "character": end },
+    })
+}
+
+#[test]
+fn test_initialize() {
+    let mut client = Client::start();
+    let response = client.request("initialize", json!({ "capabilities": {} }));
+    let capabilities = &response["result"]["capabilities"];
+    assert_eq!(capabilities["definitionProvider"], true);
+    assert_eq!(capabilities["hoverProvider"], true);
+    client.notify("initialized", json!({}));
+
+    let response = client.request("unknown/method", json!({}));
+    assert_eq!(response["error"]["code"], -32601);
+    assert!(client.stop());
+}
+
+#[test]
+fn test_diagnostics() {
+    let mut client = Client::start();
+    client.request("initialize", json!({ "capabilities": {} }));
+
+    let published = client.open("def a = 1\ndef = 2\n");
+    assert_eq!(published["method"], "textDocument/publishDiagnostics");
+    let diagnostics = published["params"]["diagnostics"].as_array().unwrap();
+    assert_eq!(diagnostics.len(), 1);
+    assert_eq!(diagnostics[0]["range"], range(1, 4, 5));
+    assert_eq!(
+        diagnostics[0]["message"],
+        "Expected token 'Term', but got 'Eq'"
+    );
+
+    client.notify(
+        "textDocument/didChange",
+        json!({
+            "textDocument": { "uri": URI, "version": 2 },
+            "contentChanges": [{ "text": "def a = 1\n" }],
+        }),
+    );
+    let published = client.receive();
+    assert_eq!(published["params"]["diagnostics"], json!([]));
+    assert!(client.stop());
+}
+
+#[test]
+fn test_navigation() {
+    let mut client = Client::start();
+    client.request("initialize", json!({ "capabilities": {} }));
+    client.open(SOURCE);
+
+    let definition = client.at("textDocument/definition", 4, 7);
+    assert_eq!(definition, json!({ "uri": URI, "range": range(1, 4, 10) }));
+    assert_eq!(client.at("textDocument/definition", 1, 13), Value::Null);
+
+    let references = client.at("textDocument/references", 1, 5);
+    let ranges: Vec<&Value> = references
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|it| &it["range"])
+        .collect();
+    assert_eq!(
+        ranges,
+        vec![&range(1, 4, 10), &range(4, 6, 12), &range(5, 4, 10)]
+    );
+    assert!(client.stop());
+}
+
+#[test]
+fn test_utf16_positions() {
+    let mut client = Client::start();
+    client.request("initialize", json!({ "capabilities": {} }));
+    // The emoji takes two UTF-16 code units
+    client.open("def sq = dup *\ndef main = \"😀\" drop sq\n");
+
+    let definition = client.at("textDocument/definition", 1, 21);
+    assert_eq!(definition, json!({ "uri": URI, "range": range(0, 4, 6) }));
+
+    let references = client.at("textDocument/references", 0, 4);
+    let ranges: Vec<&Value> = references
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|it| &it["range"])
+        .collect();
+    assert_eq!(ranges, vec![&range(0, 4, 6), &range(1, 21, 23)]);
+    assert!(client.stop());
+}
+
+#[test]
+fn test_hover_and_completion() {
+    let mut client = Client::start();
+    client.request("initialize", json!({ "capabilities": {} }));
+    client.open(SOURCE);
+
+    let hover = client.at("textDocument/hover", 5, 5);
+    assert_eq!(
+        hover["contents"]["value"],
+        "```mana\ndef square\n```\n\nSquares the top of the stack"
+    );
+    let hover = client.at("textDocument/hover", 1, 13);
+    assert!(hover["contents"]["value"]
+        .as_str()
+        .unwrap()
+        .contains("Builtin `Dup`"));
+
+    let completion = client.at("textDocument/completion", 4, 0);
+    let labels: Vec<&str> = completion
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|it| it["label"].as_str().unwrap())
+        .collect();
+    assert!(labels.starts_with(&["square", "main"]));
+    assert!(labels.contains(&"swap"));
+
+    // Plain comments aren't documentation
+    client.open("# Squares\ndef square = dup *\ndef main = square\n");
+    let hover = client.at("textDocument/hover", 2, 12);
+    assert_eq!(hover["contents"]["value"], "```mana\ndef square\n```");
+    assert!(client.stop());
+}
@@ -0,0 +1,287 @@
+use mana::lang::{
+    ast::{Definition, Expr, ExprKind, NodeId, Segment},
+    parser::Parser,
+    span::Span,
+};
+
+fn term(name: &str) -> ExprKind {
+    ExprKind::Term(name.into())
+}
+
+fn kinds(body: &[Expr]) -> Vec<ExprKind> {
+    body.iter().map(|expr| expr.kind.clone()).collect()
+}
+
+/// Names and bodies of the definitions, without their ids and spans
+fn definitions(definitions: &[Definition]) -> Vec<(&str, Vec<ExprKind>)> {
+    definitions
+        .iter()
+        .map(|d| (d.name.as_str(), kinds(&d.body)))
+        .collect()
+}
+
+#[test]
+fn test_single_line_definitions() {
+    let source = r#"
+def inc = 1 +
+def half = 0.5 *
+"#;
+
+    let ast = Parser::new(source).parse().unwrap();
+
+    assert_eq!(
+        definitions(&ast.definitions),
+        vec![
+            ("inc", vec![ExprKind::Int(1), term("+")]),
+            ("half", vec![ExprKind::Float(0.5), term("*")]),
+        ]
+    );
+}
+
+#[test]
+fn test_block_definitions() {
+    let source = r#"
+# Computes n!
+def fact2 =
+    countTo     # produce a range from 0 to n (not included)
+
+    \* reduce   # consume a seq and calculate the product of its elements
+
+def greet = "hello" 'c' { dup
+        print } call"#;
+
+    let ast = Parser::new(source).parse().unwrap();
+
+    let greet = &ast.definitions[1];
+    let ExprKind::Closure(closure) = &greet.body[2].kind else {
+        panic!("expected a closure, got {:?}", greet.body[2]);
+    };
+    assert_eq!(kinds(closure), vec![term("dup"), term("print")]);
+
+    assert_eq!(
+        definitions(&ast.definitions),
+        vec![
+            (
+                "fact2",
+                vec![term("countTo"), ExprKind::Quote("*".into()), term("reduce")]
+            ),
+            (
+                "greet",
+                vec![
+                    ExprKind::Str("hello".into()),
+                    ExprKind::Char('c'),
+                    ExprKind::Closure(closure.clone()),
+                    term("call"),
+                ]
+            ),
+        ]
+    );
+}
+
+#[test]
+fn test_spans_and_ids() {
+    let source = "def a = 1 \\+ { 2 }\ndef b = \"x{a 3}\"";
+    let ast = Parser::new(source).parse().unwrap();
+
+    let a = &ast.definitions[0];
+    assert_eq!((a.id, a.span), (NodeId(0), Span::new(0, 18)));
+    let spans: Vec<(NodeId, Span)> = a.body.iter().map(|e| (e.id, e.span)).collect();
+    assert_eq!(
+        spans,
+        vec![
+            (NodeId(1), Span::new(8, 1)),
+            (NodeId(2), Span::new(10, 2)),
+            (NodeId(3), Span::new(13, 5)),
+        ]
+    );
+    let ExprKind::Closure(closure) = &a.body[2].kind else {
+        panic!("expected a closure");
+    };
+    assert_eq!(
+        (closure[0].id, closure[0].span),
+        (NodeId(4), Span::new(15, 1))
+    );
+
+    // Interpolated code keeps counting ids, with spans in the whole source
+    let b = &ast.definitions[1];
+    assert_eq!((b.id, b.span), (NodeId(5), Span::new(19, 16)));
+    assert_eq!(
+        (b.body[0].id, b.body[0].span),
+        (NodeId(6), Span::new(27, 8))
+    );
+    let ExprKind::Interpolated(segments) = &b.body[0].kind else {
+        panic!("expected an interpolated string");
+    };
+    let Segment::Code(code) = &segments[1] else {
+        panic!("expected interpolated code");
+    };
+    let spans: Vec<(NodeId, Span)> = code.iter().map(|e| (e.id, e.span)).collect();
+    assert_eq!(
+        spans,
+        vec![(NodeId(7), Span::new(30, 1)), (NodeId(8), Span::new(32, 1))]
+    );
+
+    // Ids are stable across parses of the same source
+    assert_eq!(Parser::new(source).parse().unwrap(), ast);
+}
+
+#[test]
+fn test_stack_effects() {
+    let source = "def fact ( n -- n! ) = 1\ndef pop ( a -- ) = drop\ndef id = 0\n";
+    let ast = Parser::new(source).parse().unwrap();
+
+    let effect = ast.definitions[0].effect.as_ref().unwrap();
+    assert_eq!(effect.inputs, vec!["n"]);
+    assert_eq!(effect.outputs, vec!["n!"]);
+    assert_eq!(effect.span, Span::new(9, 11));
+    assert_eq!(
+        ast.definitions[1].effect.as_ref().map(|e| e.outputs.len()),
+        Some(0)
+    );
+    assert_eq!(ast.definitions[2].effect, None);
+
+    let messages: Vec<String> = ["def f ( a b ) = 1", "def f ( a -- b = 1"]
+        .into_iter()
+        .map(|source| Parser::new(source).parse().unwrap_err().to_string())
+        .collect();
+    assert_eq!(
+        messages,
+        vec![
+            "Expected '--' in stack effect",
+            "Expected token 'RParen', but got 'Eq'",
+        ]
+    );
+}
+
+#[test]
+fn test_collection_literals() {
+    let source = r#"
+def a = [1 [] x]
+def b = { name: "x", age: 1 2 +, }
+def c = { }
+def d = {
+    key: 1,
+    other: 2
+}
+"#;
+
+    let ast = Parser::new(source).parse().unwrap();
+    let kinds: Vec<ExprKind> = ast
+        .definitions
+        .iter()
+        .map(|d| d.body[0].kind.clone())
+        .collect();
+    let entry = |key: &str, value: Vec<ExprKind>| (key.to_string(), value);
+    let entries = |kind: &ExprKind| match kind {
+        ExprKind::Table(entries) => entries
+            .iter()
+            .map(|e| (e.key.clone(), self::kinds(&e.value)))
+            .collect(),
+        _ => vec![],
+    };
+
+    assert!(
+        matches!(&kinds[0], ExprKind::List(items) if self::kinds(items) == vec![ExprKind::Int(1), ExprKind::List(vec![]), term("x")])
+    );
+    assert_eq!(
+        entries(&kinds[1]),
+        vec![
+            entry("name", vec![ExprKind::Str("x".into())]),
+            entry("age", vec![ExprKind::Int(1), ExprKind::Int(2), term("+")]),
+        ]
+    );
+    assert_eq!(kinds[2], ExprKind::Closure(vec![]));
+    assert_eq!(
+        entries(&kinds[3]),
+        vec![
+            entry("key", vec![ExprKind::Int(1)]),
+            entry("other", vec![ExprKind::Int(2)]),
+        ]
+    );
+
+    assert!(Parser::new("def a = { key: , }").parse().is_err());
+    assert!(Parser::new("def a = [1 2").parse().is_err());
+}
+
+#[test]
+fn test_parse_errors() {
+    assert!(Parser::new("1 +").parse().is_err());
+    assert!(Parser::new("def = 1").parse().is_err());
+    assert!(Parser::new("def f = { 1").parse().is_err());
+    assert!(Parser::new("def f = }").parse().is_err());
+
+    // Interpolated code can't be empty, the error points at the braces
+    let error = Parser::new(r#"def f = "a{ }b""#).parse().unwrap_err();
+    assert_eq!(error.to_string(), "Empty string interpolation");
+    assert_eq!(error.span(), Some(Span::new(10, 3)));
+}
+
+#[test]
+fn test_error_recovery() {
+    let source = r#"
+def a = 1 @ 2
+def b = 2
+def c
+    3 +
+def d =
+    4 }
+    5
+def e = { 6
+def f = 7
+"#;
+
+    let (ast, errors) = Parser::new(source).parse_with_recovery();
+
+    let names: Vec<&str> = ast.definitions.iter().map(|d| d.name.as_str()).collect();
+    assert_eq!(names, vec!["b", "f"]);
+
+    let messages: Vec<String> = errors.iter().map(ToString::to_string).collect();
+    assert_eq!(
+        messages,
+        vec![
+            "Unexpected char '@'",
+            "Expected token 'Eq', but got 'Indent'",
+            "Expected expression but got 'RBrace'",
+            "Expected token 'RBrace', but got 'Def'",
+        ]
+    );
+}
+
+#[test]
+fn test_doc_comments() {
+    let source = r#"
+## Squares a number
+##
+## Works on ints
+def square ( n -- n ) =
+    dup *
+## Cubes
+## a number
+def cube =
+    ## Dropped, nothing is defined below it
+    dup square *
+
+def plain = 1
+## Not attached
+"#;
+    let ast = Parser::new(source).parse().unwrap();
+    let docs: Vec<Option<&str>> = ast.definitions.iter().map(|d| d.doc.as_deref()).collect();
+    assert_eq!(
+        docs,
+        vec![
+            Some("Squares a number\n\nWorks on ints"),
+            Some("Cubes\na number"),
+            None,
+        ]
+    );
+    assert_eq!(
+        kinds(&ast.definitions[0].body),
+        vec![term("dup"), term("*")]
+    );
+
+    // A comment ending a body doesn't document the next definition
+    let ast = Parser::new("def a =\n    1\n    ## about a\ndef b = 2\n")
+        .parse()
+        .unwrap();
+    assert_eq!(ast.definitions[1].doc, None);
+}
@@ -0,0 +1,100 @@
+use mana::repl::Repl;
+
+/// Feeds the lines, returns the output of the last one
+fn feed(repl: &mut Repl, lines: &[&str]) -> Option<String> {
+    lines.iter().map(|line| repl.line(line)).last().flatten()
+}
+
+#[test]
+fn test_persistent_stack() {
+    let mut repl = Repl::new();
+    assert_eq!(repl.line("1 2"), Some("[1,2]\n".into()));
+    assert_eq!(repl.line("+ dup"), Some("[3,3]\n".into()));
+    assert_eq!(repl.line(":clear"), Some("[]\n".into()));
+
+    // Errors leave the stack as the failing instruction found it
+    let output = repl.line("7 drop drop").unwrap();
+    assert!(output.starts_with("error: the stack is empty"));
+    assert!(output.ends_with("[]\n"));
+
+    assert_eq!(repl.line("def f = -> n"), None);
+    feed(&mut repl, &["    1 n /", ""]);
+    let output = repl.line("0 f").unwrap();
+    assert!(output.starts_with("error: Division by zero"), "{}", output);
+    assert_eq!(repl.line("2 f"), Some("[0]\n".into()));
+}
+
+#[test]
+fn test_definitions() {
+    let mut repl = Repl::new();
+    assert_eq!(repl.line("def square ="), None);
+    assert_eq!(repl.prompt(), "... ");
+    assert_eq!(feed(&mut repl, &["    dup *", ""]), Some("[]\n".into()));
+    assert_eq!(repl.prompt(), "> ");
+    assert_eq!(repl.line("3 square"), Some("[9]\n".into()));
+
+    // Defining a word again replaces it
+    assert_eq!(repl.line("def square = dup dup * *"), None);
+    assert_eq!(repl.line(""), Some("[9]\n".into()));
+    assert_eq!(repl.line("2 square"), Some("[9,8]\n".into()));
+
+    // Open brackets continue the input
+    assert_eq!(repl.line(":clear"), Some("[]\n".into()));
+    assert_eq!(feed(&mut repl, &["[1", "2]"]), Some("[[1,2]]\n".into()));
+
+    let output = repl.line("1 sqare").unwrap();
+    assert!(output.contains("Unknown word 'sqare'"));
+    assert!(output.contains("did you mean 'square'?"));
+}
+
+#[test]
+fn test_error_locations() {
+    let mut repl = Repl::new();
+    let output = repl.line(r#"1 2 "a{+}b""#).unwrap();
+    assert!(output.contains(" --> <repl 1>:1:8\n"), "{}", output);
+    assert!(output.contains("1 | 1 2 \"a{+}b\"\n"), "{}", output);
+
+    let output = feed(&mut repl, &["[1", " 2 frob]"]).unwrap();
+    assert!(output.contains(" --> <repl 2>:2:4\n"), "{}", output);
+
+    let output = repl.line("1 drop drop").unwrap();
+    assert!(output.contains(" --> <repl 3>:1:8\n"), "{}", output);
+    assert!(
+        output.contains("note: in #repl at <repl 3>:1:8"),
+        "{}",
+        output
+    );
+}
+
+#[test]
+fn test_commands() {
+    let mut repl = Repl::new();
+    feed(&mut repl, &["def inc = 1 +", ""]);
+    assert_eq!(
+        repl.line(":disasm inc"),
+        Some("   0  PushI(1)\n   1  Add\n".into())
+    );
+    assert!(repl
+        .line(":disasm nope")
+        .unwrap()
+        .contains("Unknown word 'nope'"));
+    assert_eq!(repl.line(":stack"), Some("[]\n".into()));
+    assert!(repl
+        .line(":frobnicate")
+        .unwrap()
+        .contains("Unknown command"));
+
+    assert_eq!(
+        repl.history(),
+        [
+            "def inc = 1 +",
+            ":disasm inc",
+            ":disasm nope",
+            ":stack",
+            ":frobnicate"
+        ]
+    );
+    assert!(!repl.exited());
+    repl.line(":quit");
+    assert!(repl.exited());
+}
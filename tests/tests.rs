@@ -1 +1,12 @@
+mod compiler;
+mod diagnostic;
+mod doc;
+mod formatter;
+mod lexer;
+mod loader;
+mod lsp;
+mod parser;
+mod repl;
+mod trace;
+mod trivia;
 mod vm;
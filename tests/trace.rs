@@ -0,0 +1,119 @@
+use mana::{
+    lang::lexer::Lexer,
+    trace::{Event, JsonTracer, Tracer},
+    vm::{emitter::Emitter, function::Functions, VM},
+};
+use serde_json::{json, Value};
+use std::{cell::RefCell, rc::Rc};
+
+/// Keeps a short description of every event, shared with the test
+#[derive(Debug, Default, Clone)]
+struct Recorder(Rc<RefCell<Vec<String>>>);
+
+impl Tracer for Recorder {
+    fn trace(&mut self, event: &Event) {
+        let line = match event {
+            Event::Token(token) => format!("token {}", token.kind),
+            Event::Instruction {
+                function,
+                addr,
+                stack,
+                ..
+            } => format!("{}@{} {}", function, addr, stack),
+            Event::Call { function } => format!("call {}", function),
+            Event::Return { function } => format!("return {}", function),
+            Event::Error(error) => format!("error {}", error),
+        };
+        self.0.borrow_mut().push(line);
+    }
+}
+
+#[test]
+fn test_vm_events() {
+    let double = {
+        let mut e = Emitter::new();
+        e.dup().add();
+        e.finish()
+    };
+    let main_fn = {
+        let mut e = Emitter::new();
+        e.push_int(2)
+            .push_function_ref("double")
+            .call()
+            .drop()
+            .drop();
+        e.finish()
+    };
+    let mut functions = Functions::new();
+    functions.insert("double".into(), double);
+    functions.insert("main".into(), main_fn);
+
+    let recorder = Recorder::default();
+    let mut vm = VM::new(functions);
+    vm.set_tracer(recorder.clone());
+    assert!(vm.run("main").is_err());
+
+    assert_eq!(
+        *recorder.0.borrow(),
+        vec![
+            "call main",
+            "main@0 [2]",
+            "main@1 [2,double]",
+            "call double",
+            "double@0 [2,2]",
+            "double@1 [4]",
+            "return double",
+            "main@2 [4]",
+            "main@3 []",
+            "error the stack is empty",
+        ]
+    );
+}
+
+#[test]
+fn test_json_lines() {
+    let mut tracer = JsonTracer::new(vec![]);
+    let mut lexer = Lexer::new("def a = @");
+    lexer.set_tracer(&mut tracer);
+    let errors = lexer.by_ref().filter(Result::is_err).count();
+    drop(lexer);
+    assert_eq!(errors, 1);
+
+    let out = String::from_utf8(tracer.into_inner()).unwrap();
+    let events: Vec<Value> = out
+        .lines()
+        .map(|line| serde_json::from_str(line).unwrap())
+        .collect();
+    assert_eq!(
+        events,
+        vec![
+            json!({ "event": "token", "kind": "Def", "start": 0, "length": 3 }),
+            json!({ "event": "token", "kind": "Term", "start": 4, "length": 1 }),
+            json!({ "event": "token", "kind": "Eq", "start": 6, "length": 1 }),
+            json!({ "event": "error", "message": "Unexpected char '@'" }),
+            json!({ "event": "token", "kind": "Eof", "start": 9, "length": 0 }),
+        ]
+    );
+}
+
+#[test]
+fn test_borrowed_vm_tracer() {
+    let main_fn = {
+        let mut e = Emitter::new();
+        e.push_int(1);
+        e.finish()
+    };
+    let mut functions = Functions::new();
+    functions.insert("main".into(), main_fn);
+
+    let mut tracer = JsonTracer::new(vec![]);
+    let mut vm = VM::new(functions);
+    vm.set_tracer(&mut tracer);
+    vm.run("main").unwrap();
+    drop(vm);
+
+    let out = String::from_utf8(tracer.into_inner()).unwrap();
+    let events: Vec<&str> = out.lines().collect();
+    assert_eq!(events.len(), 3);
+    assert!(events[0].contains(r#""event":"call""#));
+}
@@ -0,0 +1,61 @@
+use mana::lang::{
+    token::TokenKind,
+    trivia::{to_source, tokenize_lossless, TriviaKind},
+};
+
+const SOURCE: &str = r#"# Module header
+
+import Math   # trailing
+
+def fact2 =
+    countTo       # produce a range
+        \* reduce
+  
+    "multi
+line" 'c' { 1   2 }   
+
+   # indented comment
+def b = 0xFF -1.5e3"#;
+
+#[test]
+fn test_round_trip() {
+    let tokens = tokenize_lossless(SOURCE).unwrap();
+    assert_eq!(to_source(&tokens), SOURCE);
+    assert_eq!(tokens.last().unwrap().kind(), TokenKind::Eof);
+
+    let source = "def a =\n    1\n";
+    assert_eq!(to_source(&tokenize_lossless(source).unwrap()), source);
+}
+
+#[test]
+fn test_trivia_attachment() {
+    let tokens = tokenize_lossless(SOURCE).unwrap();
+
+    let import = &tokens[0];
+    assert_eq!(import.kind(), TokenKind::Import);
+    let leading: Vec<(TriviaKind, &str)> =
+        import.leading.iter().map(|t| (t.kind, t.text)).collect();
+    assert_eq!(
+        leading,
+        vec![
+            (TriviaKind::Comment, "# Module header"),
+            (TriviaKind::Newline, "\n"),
+            (TriviaKind::Newline, "\n"),
+        ]
+    );
+
+    let math = &tokens[1];
+    assert_eq!(math.text, "Math");
+    let trailing: Vec<&str> = math.trailing.iter().map(|t| t.text).collect();
+    assert_eq!(trailing, vec!["   ", "# trailing", "\n"]);
+
+    let def_b = tokens
+        .iter()
+        .rev()
+        .find(|t| t.kind() == TokenKind::Def)
+        .unwrap();
+    assert!(def_b
+        .leading
+        .iter()
+        .any(|t| t.kind == TriviaKind::Comment && t.text == "# indented comment"));
+}
@@ -33,7 +33,7 @@ fn test_factorial() {
     let mut vm = VM::new(functions);
 
     vm.push(MetaValue::int(5));
-    vm.run("factorial");
+    vm.run("factorial").unwrap();
 
     assert_eq!(vm.pop(), Ok(MetaValue::int(120)));
 }
@@ -73,7 +73,7 @@ fn test_closure() {
 
     let mut vm = VM::new(functions);
 
-    vm.run("main");
+    vm.run("main").unwrap();
 
     assert_eq!(vm.pop(), Ok(MetaValue::int(3)));
 }
@@ -85,16 +85,6 @@ fn test_functions() {
         e.push_int(1).add();
         e.finish()
     };
-    let add = {
-        let mut e = Emitter::new();
-        e.add();
-        e.finish()
-    };
-    let add = {
-        let mut e = Emitter::new();
-        e.add();
-        e.finish()
-    };
     let list_inc = {
         let mut e = Emitter::new();
         e.push_function_ref("inc")
@@ -151,10 +141,52 @@ fn test_functions() {
     let mut vm = VM::new(functions);
 
     vm.push(MetaValue::list(vec![5.into(), 6.into()]));
-    vm.run("List.inc");
+    vm.run("List.inc").unwrap();
 
     let res = vm.pop();
     let expected = Ok(MetaValue::list(vec![6.into(), 7.into()]));
 
     assert_eq!(res, expected);
 }
+
+#[test]
+fn test_nested_loops() {
+    let mut e = Emitter::new();
+
+    let (i, j, total) = (e.local_new(), e.local_new(), e.local_new());
+
+    e.push_int(0).local_store(total);
+    e.push_int(3).local_store(i);
+    e.while_loop(
+        |e| {
+            e.local_load(i).push_int(0).greater_than();
+        },
+        |e| {
+            e.push_int(2).local_store(j);
+            e.while_loop(
+                |e| {
+                    e.local_load(j).push_int(0).greater_than();
+                },
+                |e| {
+                    e.local_load(total)
+                        .push_int(1)
+                        .add()
+                        .local_store(total)
+                        .local_load(j)
+                        .push_int(1)
+                        .sub()
+                        .local_store(j);
+                },
+            );
+            e.local_load(i).push_int(1).sub().local_store(i);
+        },
+    );
+    e.local_load(total);
+
+    let mut functions = Functions::new();
+    functions.insert("main".into(), e.finish());
+    let mut vm = VM::new(functions);
+    vm.run("main").unwrap();
+
+    assert_eq!(vm.pop(), Ok(MetaValue::int(6)));
+}